@@ -0,0 +1,71 @@
+// Instructions per second of a headless run of Brix, through `Chip8::step` and its decode cache,
+// through the uncached decoding `step` used before, for comparison, and through the block backend.
+// `cargo bench --no-default-features` runs it without needing SDL2.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use chip8::Chip8;
+use chip8::blocks::Backend;
+use chip8::cpu::ExecutionError;
+use chip8::quirks::Quirks;
+use chip8::random::RandomSource;
+
+const ROM: &[u8] = include_bytes!("../roms/Brix [Andreas Gustafsson, 1990].ch8");
+const FRAMES: u32 = 60;
+// the SUPER-CHIP quirks don't wait for the display, so every frame runs all of its instructions
+const INSTRUCTIONS_PER_FRAME: u32 = 1000;
+
+fn machine() -> Chip8 {
+    let mut system = Chip8::new(Quirks::SCHIP);
+    system.load_program(ROM);
+    system.cpu.set_random_source(RandomSource::seeded(0));
+    system
+}
+
+// `Chip8::step` without the decode cache
+fn uncached_step(system: &mut Chip8) -> Result<(), ExecutionError> {
+    if system.cpu.is_halted() || system.cpu.is_waiting_for_vblank() {
+        return Ok(());
+    }
+    let pc = system.cpu.program_counter;
+    if pc as usize + 2 > system.ram.size() {
+        return Err(ExecutionError::MemoryOutOfRange { address: pc as usize, length: 2 });
+    }
+    let inst = system.ram.read_instruction(pc as usize)
+        .map_err(|e| ExecutionError::InvalidInstruction { address: pc, opcode: e.opcode() })?;
+    if pc as usize + inst.length() as usize > system.ram.size() {
+        return Err(ExecutionError::MemoryOutOfRange { address: pc as usize, length: inst.length() as usize });
+    }
+    system.cpu.execute(&mut system.ram, &system.keyboard, &mut system.display, &system.quirks, inst)
+}
+
+fn run(step: fn(&mut Chip8) -> Result<(), ExecutionError>) -> Chip8 {
+    let mut system = machine();
+    for _ in 0..FRAMES {
+        for _ in 0..INSTRUCTIONS_PER_FRAME {
+            step(&mut system).unwrap();
+        }
+        system.cpu.countdown_timers();
+    }
+    system
+}
+
+fn run_blocks() -> Chip8 {
+    let mut system = machine();
+    system.backend = Backend::Blocks;
+    for _ in 0..FRAMES {
+        system.run_frame(INSTRUCTIONS_PER_FRAME).unwrap();
+    }
+    system
+}
+
+fn interpreter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("brix");
+    group.throughput(Throughput::Elements((FRAMES * INSTRUCTIONS_PER_FRAME) as u64));
+    group.bench_function("uncached", |b| b.iter(|| black_box(run(uncached_step))));
+    group.bench_function("decode cache", |b| b.iter(|| black_box(run(Chip8::step))));
+    group.bench_function("blocks", |b| b.iter(|| black_box(run_blocks())));
+    group.finish();
+}
+
+criterion_group!(benches, interpreter);
+criterion_main!(benches);
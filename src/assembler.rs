@@ -0,0 +1,465 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::instructions::Instruction;
+use crate::memory::RAM_INTPT_OFFSET;
+
+// how deep `include`s and `define`s referring to other defines may nest
+const MAX_NESTING: usize = 32;
+
+pub struct AssembleError {
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String
+}
+
+impl fmt::Debug for AssembleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}:{}: {}", file, self.line, self.column, self.message),
+            None => write!(f, "{}:{}: {}", self.line, self.column, self.message)
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+#[derive(Clone)]
+struct Location {
+    file: Option<String>,
+    line: usize,
+    column: usize
+}
+
+impl Location {
+    fn error(&self, message: impl Into<String>) -> AssembleError {
+        AssembleError { file: self.file.clone(), line: self.line, column: self.column, message: message.into() }
+    }
+
+    fn at_column(&self, column: usize) -> Location {
+        Location { column, ..self.clone() }
+    }
+}
+
+#[derive(Clone)]
+struct Token {
+    text: String,
+    column: usize
+}
+
+fn tokenize(line: &str, location: &Location) -> Result<Vec<Token>, AssembleError> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = line.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            i += 1;
+            continue;
+        } else if c == '"' {
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                i += 1;
+            }
+            if i == chars.len() {
+                return Err(location.at_column(start + 1).error("unterminated string"));
+            }
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+        } else if ",:[]+-".contains(c) {
+            i += 1;
+        } else {
+            return Err(location.at_column(start + 1).error(format!("unexpected character '{}'", c)));
+        }
+        tokens.push(Token { text: chars[start..i].iter().collect(), column: start + 1 });
+    }
+    Ok(tokens)
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.chars().next().map(|c| c.is_ascii_alphabetic() || c == '_' || c == '.').unwrap_or(false)
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+// a sum of numbers and symbols, e.g. `sprites + 5`
+#[derive(Clone)]
+struct Expr {
+    terms: Vec<(bool, Token)>,
+    location: Location
+}
+
+fn parse_expr(tokens: &[Token], location: &Location) -> Result<Expr, AssembleError> {
+    let location = location.at_column(tokens.first().map(|t| t.column).unwrap_or(location.column));
+    if tokens.is_empty() {
+        return Err(location.error("expected a value"));
+    }
+
+    let mut terms = Vec::new();
+    let mut negative = false;
+    let mut expect_term = true;
+    for token in tokens {
+        match token.text.as_str() {
+            "+" | "-" if expect_term => negative ^= token.text == "-",
+            "+" | "-" => {
+                negative = token.text == "-";
+                expect_term = true;
+            }
+            _ if expect_term && (parse_number(&token.text).is_some() || is_identifier(&token.text)) => {
+                terms.push((negative, token.clone()));
+                negative = false;
+                expect_term = false;
+            }
+            _ => return Err(location.at_column(token.column).error(format!("unexpected \"{}\"", token.text)))
+        }
+    }
+    if expect_term {
+        return Err(location.at_column(tokens.last().unwrap().column).error("expected a value"));
+    }
+    Ok(Expr { terms, location })
+}
+
+enum Symbol {
+    Address(u16),
+    Define(Expr)
+}
+
+struct SymbolTable {
+    symbols: HashMap<String, Symbol>
+}
+
+impl SymbolTable {
+    fn evaluate(&self, expr: &Expr, depth: usize) -> Result<i64, AssembleError> {
+        if depth > MAX_NESTING {
+            return Err(expr.location.error("defines nest too deeply (or refer to themselves)"));
+        }
+        let mut value: i64 = 0;
+        for (negative, token) in &expr.terms {
+            let term = match parse_number(&token.text) {
+                Some(number) => number,
+                None => match self.symbols.get(&token.text) {
+                    Some(Symbol::Address(address)) => *address as i64,
+                    Some(Symbol::Define(inner)) => self.evaluate(inner, depth + 1)?,
+                    None => return Err(expr.location.at_column(token.column).error(format!("unknown symbol \"{}\"", token.text)))
+                }
+            };
+            value = if *negative { term.checked_neg() } else { Some(term) }
+                .and_then(|term| value.checked_add(term))
+                .ok_or_else(|| expr.location.at_column(token.column).error("value is too large"))?;
+        }
+        Ok(value)
+    }
+
+    fn evaluate_range(&self, expr: &Expr, min: i64, max: i64, what: &str) -> Result<i64, AssembleError> {
+        let value = self.evaluate(expr, 0)?;
+        if value < min || value > max {
+            return Err(expr.location.error(format!("{} {} is out of range ({} to {})", what, value, min, max)));
+        }
+        Ok(value)
+    }
+}
+
+enum Operand {
+    V(u8),
+    I,
+    IndirectI,
+    DT,
+    ST,
+    K,
+    F,
+    HF,
+    B,
+    R,
+    Long(Expr),
+    Value(Expr)
+}
+
+fn parse_operand(tokens: &[Token], location: &Location) -> Result<Operand, AssembleError> {
+    let location = location.at_column(tokens.first().map(|t| t.column).unwrap_or(location.column));
+    let texts: Vec<String> = tokens.iter().map(|t| t.text.to_ascii_uppercase()).collect();
+    let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+    match texts[..] {
+        [register] if register.len() == 2 && register.starts_with('V') => {
+            match u8::from_str_radix(&register[1..], 16) {
+                Ok(index) => Ok(Operand::V(index)),
+                Err(_) => Ok(Operand::Value(parse_expr(tokens, &location)?))
+            }
+        }
+        ["I"] => Ok(Operand::I),
+        ["[", "I", "]"] => Ok(Operand::IndirectI),
+        ["DT"] => Ok(Operand::DT),
+        ["ST"] => Ok(Operand::ST),
+        ["K"] => Ok(Operand::K),
+        ["F"] => Ok(Operand::F),
+        ["HF"] => Ok(Operand::HF),
+        ["B"] => Ok(Operand::B),
+        ["R"] => Ok(Operand::R),
+        ["LONG", ..] => Ok(Operand::Long(parse_expr(&tokens[1..], &location)?)),
+        _ => Ok(Operand::Value(parse_expr(tokens, &location)?))
+    }
+}
+
+fn split_operands(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+    tokens.split(|t| t.text == ",").collect()
+}
+
+enum StatementKind {
+    Instruction { mnemonic: String, operands: Vec<Operand> },
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>)
+}
+
+struct Statement {
+    kind: StatementKind,
+    location: Location
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match &self.kind {
+            StatementKind::Instruction { mnemonic, operands } => {
+                if mnemonic == "LD" && matches!(operands.get(1), Some(Operand::Long(_))) { 4 } else { 2 }
+            }
+            StatementKind::Bytes(values) => values.len(),
+            StatementKind::Words(values) => values.len() * 2
+        }
+    }
+}
+
+struct Assembler {
+    statements: Vec<Statement>,
+    symbols: SymbolTable,
+    address: usize
+}
+
+impl Assembler {
+    fn define(&mut self, name: &Token, symbol: Symbol, location: &Location) -> Result<(), AssembleError> {
+        if !is_identifier(&name.text) {
+            return Err(location.at_column(name.column).error(format!("invalid symbol name \"{}\"", name.text)));
+        }
+        if self.symbols.symbols.contains_key(&name.text) {
+            return Err(location.at_column(name.column).error(format!("\"{}\" is already defined", name.text)));
+        }
+        self.symbols.symbols.insert(name.text.clone(), symbol);
+        Ok(())
+    }
+
+    fn parse_source(&mut self, source: &str, file: Option<&Path>, depth: usize) -> Result<(), AssembleError> {
+        let file_name = file.map(|path| path.display().to_string());
+        for (line_index, line) in source.lines().enumerate() {
+            let location = Location { file: file_name.clone(), line: line_index + 1, column: 1 };
+            let mut tokens = &tokenize(line, &location)?[..];
+
+            // any number of labels may precede a statement
+            while tokens.len() >= 2 && tokens[1].text == ":" {
+                let label = tokens[0].clone();
+                if self.address > u16::MAX as usize {
+                    return Err(location.at_column(label.column).error("label is past the end of memory"));
+                }
+                self.define(&label, Symbol::Address(self.address as u16), &location)?;
+                tokens = &tokens[2..];
+            }
+            let (keyword, rest) = match tokens.split_first() {
+                Some(split) => split,
+                None => continue
+            };
+            let location = location.at_column(keyword.column);
+
+            match keyword.text.to_ascii_lowercase().as_str() {
+                "define" => {
+                    let (name, value) = rest.split_first().ok_or_else(|| location.error("define expects a name and a value"))?;
+                    let value = parse_expr(value, &location.at_column(name.column))?;
+                    self.define(name, Symbol::Define(value), &location)?;
+                }
+                "include" => {
+                    let path = match rest {
+                        [token] if token.text.starts_with('"') => &token.text[1..token.text.len()-1],
+                        _ => return Err(location.error("include expects a quoted file name"))
+                    };
+                    if depth >= MAX_NESTING {
+                        return Err(location.error("includes nest too deeply"));
+                    }
+                    let path = match file.and_then(Path::parent) {
+                        Some(directory) => directory.join(path),
+                        None => PathBuf::from(path)
+                    };
+                    let source = fs::read_to_string(&path)
+                        .map_err(|e| location.error(format!("couldn't include \"{}\": {}", path.display(), e)))?;
+                    self.parse_source(&source, Some(&path), depth + 1)?;
+                }
+                "db" | "dw" => {
+                    let values = split_operands(rest).into_iter()
+                        .map(|tokens| parse_expr(tokens, &location))
+                        .collect::<Result<Vec<Expr>, AssembleError>>()?;
+                    let kind = if keyword.text.eq_ignore_ascii_case("db") { StatementKind::Bytes(values) } else { StatementKind::Words(values) };
+                    self.push(Statement { kind, location })?;
+                }
+                _ => {
+                    let operands = split_operands(rest).into_iter()
+                        .map(|tokens| parse_operand(tokens, &location))
+                        .collect::<Result<Vec<Operand>, AssembleError>>()?;
+                    let mnemonic = keyword.text.to_ascii_uppercase();
+                    self.push(Statement { kind: StatementKind::Instruction { mnemonic, operands }, location })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, statement: Statement) -> Result<(), AssembleError> {
+        self.address += statement.size();
+        if self.address > u16::MAX as usize + 1 {
+            return Err(statement.location.error("program doesn't fit in memory"));
+        }
+        self.statements.push(statement);
+        Ok(())
+    }
+
+    fn emit(&self) -> Result<Vec<u8>, AssembleError> {
+        let mut out = Vec::new();
+        for statement in &self.statements {
+            match &statement.kind {
+                StatementKind::Instruction { mnemonic, operands } => {
+                    let inst = self.build_instruction(mnemonic, operands, &statement.location)?;
+                    inst.write(&mut out);
+                }
+                StatementKind::Bytes(values) => {
+                    for value in values {
+                        out.push(self.symbols.evaluate_range(value, -128, 0xFF, "byte")? as u8);
+                    }
+                }
+                StatementKind::Words(values) => {
+                    for value in values {
+                        let word = self.symbols.evaluate_range(value, -0x8000, 0xFFFF, "word")? as u16;
+                        out.extend_from_slice(&word.to_be_bytes());
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    fn build_instruction(&self, mnemonic: &str, operands: &[Operand], location: &Location) -> Result<Instruction, AssembleError> {
+        use Operand::*;
+        let symbols = &self.symbols;
+        let address = |expr: &Expr| symbols.evaluate_range(expr, 0, 0xFFF, "address").map(|v| v as u16);
+        let byte = |expr: &Expr| symbols.evaluate_range(expr, -128, 0xFF, "byte").map(|v| v as u8);
+        let nibble = |expr: &Expr| symbols.evaluate_range(expr, 0, 0xF, "value").map(|v| v as u8);
+        let registers_only = |f: fn(u8, u8) -> Instruction| match operands {
+            [V(x), V(y)] => Ok(f(*x, *y)),
+            _ => Err(location.error(format!("{} expects two registers", mnemonic)))
+        };
+
+        let inst = match (mnemonic, operands) {
+            ("CLS", []) => Instruction::CLS,
+            ("RET", []) => Instruction::RET,
+            ("SCR", []) => Instruction::SCR,
+            ("SCL", []) => Instruction::SCL,
+            ("EXIT", []) => Instruction::EXIT,
+            ("LOW", []) => Instruction::LOW,
+            ("HIGH", []) => Instruction::HIGH,
+            ("AUDIO", []) => Instruction::AUDIO,
+            ("SYS", [Value(n)]) => Instruction::SYS(address(n)?),
+            ("SCD", [Value(n)]) => Instruction::SCD(nibble(n)?),
+            ("JP", [Value(n)]) => Instruction::JP(address(n)?),
+            ("JP", [V(0), Value(n)]) => Instruction::JP_RV(address(n)?),
+            ("CALL", [Value(n)]) => Instruction::CALL(address(n)?),
+            ("SE", [V(x), Value(v)]) => Instruction::SE_RV(*x, byte(v)?),
+            ("SE", [V(x), V(y)]) => Instruction::SE_RR(*x, *y),
+            ("SNE", [V(x), Value(v)]) => Instruction::SNE_RV(*x, byte(v)?),
+            ("SNE", [V(x), V(y)]) => Instruction::SNE_RR(*x, *y),
+            ("SAVE", _) => registers_only(Instruction::LD_IRXY)?,
+            ("LOAD", _) => registers_only(Instruction::LD_RXYI)?,
+            ("LD", [V(x), Value(v)]) => Instruction::LD_RV(*x, byte(v)?),
+            ("LD", [V(x), V(y)]) => Instruction::LD_RR(*x, *y),
+            ("LD", [I, Value(n)]) => Instruction::LD_IV(address(n)?),
+            ("LD", [I, Long(n)]) => Instruction::LD_IL(symbols.evaluate_range(n, 0, 0xFFFF, "address")? as u16),
+            ("LD", [V(x), DT]) => Instruction::LD_RD(*x),
+            ("LD", [V(x), K]) => Instruction::LD_RK(*x),
+            ("LD", [DT, V(x)]) => Instruction::LD_DR(*x),
+            ("LD", [ST, V(x)]) => Instruction::LD_SR(*x),
+            ("LD", [F, V(x)]) => Instruction::LD_RF(*x),
+            ("LD", [HF, V(x)]) => Instruction::LD_RHF(*x),
+            ("LD", [B, V(x)]) => Instruction::LD_BR(*x),
+            ("LD", [IndirectI, V(x)]) => Instruction::LD_IRR(*x),
+            ("LD", [V(x), IndirectI]) => Instruction::LD_RRI(*x),
+            ("LD", [R, V(x)]) => Instruction::LD_PRR(*x),
+            ("LD", [V(x), R]) => Instruction::LD_RRP(*x),
+            ("ADD", [V(x), Value(v)]) => Instruction::ADD_RV(*x, byte(v)?),
+            ("ADD", [V(x), V(y)]) => Instruction::ADD_RR(*x, *y),
+            ("ADD", [I, V(x)]) => Instruction::ADD_IR(*x),
+            ("OR", _) => registers_only(Instruction::OR)?,
+            ("AND", _) => registers_only(Instruction::AND)?,
+            ("XOR", _) => registers_only(Instruction::XOR)?,
+            ("SUB", _) => registers_only(Instruction::SUB)?,
+            ("SUBN", _) => registers_only(Instruction::SUBN)?,
+            // the source register defaults to the shifted one
+            ("SHR", [V(x)]) => Instruction::SHR(*x, *x),
+            ("SHR", _) => registers_only(Instruction::SHR)?,
+            ("SHL", [V(x)]) => Instruction::SHL(*x, *x),
+            ("SHL", _) => registers_only(Instruction::SHL)?,
+            ("RND", [V(x), Value(v)]) => Instruction::RND(*x, byte(v)?),
+            ("DRW", [V(x), V(y), Value(n)]) => Instruction::DRW(*x, *y, nibble(n)?),
+            ("SKP", [V(x)]) => Instruction::SKP(*x),
+            ("SKNP", [V(x)]) => Instruction::SKNP(*x),
+            ("PLANE", [Value(n)]) => Instruction::PLANE(nibble(n)?),
+            ("PITCH", [V(x)]) => Instruction::PITCH(*x),
+            _ => return Err(location.error(format!("invalid instruction or operands for \"{}\"", mnemonic)))
+        };
+        Ok(inst)
+    }
+}
+
+fn assemble_source(source: &str, file: Option<&Path>) -> Result<Vec<u8>, AssembleError> {
+    let mut assembler = Assembler {
+        statements: Vec::new(),
+        symbols: SymbolTable { symbols: HashMap::new() },
+        address: RAM_INTPT_OFFSET
+    };
+    assembler.parse_source(source, file, 0)?;
+    assembler.emit()
+}
+
+// assembles a program to be loaded at the start of program memory, includes are
+// resolved relative to the working directory
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    assemble_source(source, None)
+}
+
+// includes are resolved relative to the including file
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AssembleError> {
+    let source = fs::read_to_string(path).map_err(|e| AssembleError {
+        file: Some(path.display().to_string()),
+        line: 0,
+        column: 0,
+        message: format!("couldn't read source: {}", e)
+    })?;
+    assemble_source(&source, Some(path))
+}
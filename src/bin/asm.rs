@@ -0,0 +1,29 @@
+use std::{env, fs};
+use std::path::{Path, PathBuf};
+use chip8::assembler;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let (source_path, output_path) = match &args[1..] {
+        [source] => (Path::new(source), Path::new(source).with_extension("ch8")),
+        [source, flag, output] if flag == "-o" => (Path::new(source), PathBuf::from(output)),
+        _ => {
+            println!("Invalid Syntax: {} <source_path> [-o <rom_path>]", args[0]);
+            return;
+        }
+    };
+
+    let program = match assembler::assemble_file(source_path) {
+        Ok(program) => program,
+        Err(e) => {
+            println!("ERROR: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = fs::write(&output_path, &program) {
+        println!("ERROR: Couldn't write ROM \"{}\"", e);
+        std::process::exit(1);
+    }
+    println!("Assembled \"{}\" into \"{}\". ({} bytes)", source_path.display(), output_path.display(), program.len());
+}
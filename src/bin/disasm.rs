@@ -0,0 +1,22 @@
+use std::{env, fs};
+use chip8::disassembler;
+use chip8::memory::RAM_INTPT_OFFSET;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 2 {
+        println!("Invalid Syntax: {} <rom_path>", args[0]);
+        return;
+    }
+
+    let rom_data = match fs::read(&args[1]) {
+        Ok(rom_data) => rom_data,
+        Err(e) => {
+            println!("ERROR: Couldn't read ROM \"{}\"", e);
+            return;
+        }
+    };
+
+    println!("; {} ({} bytes)", args[1], rom_data.len());
+    print!("{}", disassembler::disassemble(&rom_data, RAM_INTPT_OFFSET as u16));
+}
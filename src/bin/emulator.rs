@@ -1,85 +1,513 @@
-use std::{env, fs, thread};
-use std::path::Path;
-use std::time::Duration;
-use sdl2::audio::{AudioCallback, AudioSpecDesired};
+use std::collections::{HashMap, HashSet};
+use std::{env, fs, io, thread};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use sdl2::{EventPump, GameControllerSubsystem};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::controller::{Axis, Button, GameController};
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
-use chip8::{Chip8, display, memory};
-use chip8::cpu::Coordinator;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use chip8::{Chip8, memory};
+use chip8::config::{Hotkey, KeyConfig};
+use chip8::cpu::AUDIO_PATTERN_SIZE;
+use chip8::debugger::DebugCommand;
+use chip8::display::Display;
+use chip8::frontend::{AudioSink, InputEvent, InputSource, VideoSink};
 use chip8::keyboard::KeyEvent::{Pressed, Released};
+use chip8::movie;
+use chip8::movie::{Movie, RngKind};
+use chip8::quirks::Quirks;
+use chip8::random::RandomSource;
+use chip8::romdb::{self, RomDatabase, RomInfo};
+use chip8::runner::{MovieMode, Runner};
+use chip8::timing;
 
 const SCREEN_WIDTH: u32 = 960;
 const SCREEN_HEIGHT: u32 = 480;
 const COLOR_CLEAR: Color = Color::RGB(0, 0, 0);
 const COLOR_CONTRAST: Color = Color::RGB(255, 255, 255);
+// indexed by the XO-CHIP plane mask of a pixel
+const PLANE_COLORS: [Color; 4] = [
+    COLOR_CLEAR,
+    COLOR_CONTRAST,
+    Color::RGB(170, 170, 170),
+    Color::RGB(85, 85, 85),
+];
 
-const CYCLES_PER_SECOND: u32 = 500;
 
-const KEYPAD_TABLE: [Keycode; 16] = [
-    Keycode::Num1, Keycode::Num2, Keycode::Num3, Keycode::Num4,
-    Keycode::Q,    Keycode::W,    Keycode::E,    Keycode::R,
-    Keycode::A,    Keycode::S,    Keycode::D,    Keycode::F,
-    Keycode::Z,    Keycode::X,    Keycode::C,    Keycode::V,
+const STATE_SLOT_KEYS: [Keycode; 10] = [
+    Keycode::Num0, Keycode::Num1, Keycode::Num2, Keycode::Num3, Keycode::Num4,
+    Keycode::Num5, Keycode::Num6, Keycode::Num7, Keycode::Num8, Keycode::Num9,
 ];
 
-struct SquareWave {
+// plays a 440Hz square wave, or the XO-CHIP audio pattern once a program has loaded one
+struct ToneGenerator {
     phase_inc: f32,
     phase: f32,
-    volume: f32
+    volume: f32,
+    freq: f32,
+    pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    pattern_inc: f32,
+    pattern_position: f32
 }
 
-impl AudioCallback for SquareWave {
+impl AudioCallback for ToneGenerator {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [f32]) {
         for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
+            let high = match self.pattern {
+                Some(pattern) => {
+                    let bit = self.pattern_position as usize;
+                    self.pattern_position = (self.pattern_position + self.pattern_inc) % (AUDIO_PATTERN_SIZE * 8) as f32;
+                    pattern[bit / 8] & (0x80 >> (bit % 8)) != 0
+                }
+                None => {
+                    self.phase = (self.phase + self.phase_inc) % 1.0;
+                    self.phase <= 0.5
+                }
+            };
+            *x = if high {
                 self.volume
             } else {
                 -self.volume
             };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
         }
     }
 }
 
+struct SdlAudio {
+    device: AudioDevice<ToneGenerator>
+}
+
+impl AudioSink for SdlAudio {
+    fn set_tone(&mut self, on: bool) {
+        if on {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: [u8; AUDIO_PATTERN_SIZE], playback_rate: f32) {
+        let mut generator = self.device.lock();
+        generator.pattern = Some(pattern);
+        generator.pattern_inc = playback_rate / generator.freq;
+    }
+}
+
+struct SdlVideo {
+    canvas: Canvas<Window>
+}
+
+impl VideoSink for SdlVideo {
+    fn present(&mut self, display: &Display) {
+        self.canvas.set_draw_color(COLOR_CLEAR);
+        self.canvas.clear();
+
+        let display_data = display.borrow_display();
+
+        let rect_width = SCREEN_WIDTH / display.width() as u32;
+        let rect_height = SCREEN_HEIGHT / display.height() as u32;
+
+        let mut rects = vec![Vec::new(); PLANE_COLORS.len()];
+        for x in 0..display.width() as i32 {
+            for y in 0..display.height() as i32 {
+                let planes = display_data[x as usize][y as usize] as usize;
+                if planes != 0 {
+                    rects[planes].push(Rect::new(x * rect_width as i32, y * rect_height as i32, rect_width, rect_height));
+                }
+            }
+        }
+        for (color, rects) in PLANE_COLORS.iter().zip(rects.iter()).skip(1) {
+            self.canvas.set_draw_color(*color);
+            self.canvas.fill_rects(&rects[..]).ok();
+        }
+        self.canvas.present();
+    }
+}
+
+// the config's names resolved to SDL2 keycodes, controller buttons and stick directions
+struct KeyBindings {
+    keypad: HashMap<Keycode, u8>,
+    hotkeys: HashMap<Keycode, Hotkey>,
+    buttons: HashMap<Button, u8>,
+    // an axis along with the direction it's pushed in, true for positive
+    axes: HashMap<(Axis, bool), u8>,
+    deadzone: f32
+}
+
+impl KeyBindings {
+    fn resolve(config: &KeyConfig) -> Result<KeyBindings, String> {
+        let keycode = |name: &str| Keycode::from_name(name).ok_or(format!("unknown key \"{}\"", name));
+        let mut bindings = KeyBindings {
+            keypad: HashMap::new(),
+            hotkeys: HashMap::new(),
+            buttons: HashMap::new(),
+            axes: HashMap::new(),
+            deadzone: config.deadzone
+        };
+        for (&key, names) in &config.keys {
+            for name in names {
+                bindings.keypad.insert(keycode(name)?, key);
+            }
+        }
+        for (&hotkey, name) in &config.hotkeys {
+            bindings.hotkeys.insert(keycode(name)?, hotkey);
+        }
+        for (&key, names) in &config.buttons {
+            for name in names {
+                let direction = name.strip_suffix('+').map(|axis| (axis, true))
+                    .or_else(|| name.strip_suffix('-').map(|axis| (axis, false)));
+                match direction {
+                    Some((axis, positive)) => {
+                        let axis = Axis::from_string(axis).ok_or(format!("unknown gamepad axis \"{}\"", axis))?;
+                        bindings.axes.insert((axis, positive), key);
+                    }
+                    None => {
+                        let button = Button::from_string(name).ok_or(format!("unknown gamepad button \"{}\"", name))?;
+                        bindings.buttons.insert(button, key);
+                    }
+                }
+            }
+        }
+        Ok(bindings)
+    }
+
+    fn hotkey(&self, keycode: Keycode) -> Option<Hotkey> {
+        self.hotkeys.get(&keycode).copied()
+    }
+
+    // the keys a gamepad can hold down
+    fn gamepad_keys(&self) -> impl Iterator<Item=&u8> {
+        self.buttons.values().chain(self.axes.values())
+    }
+}
+
+// the game controllers that are plugged in, opened as they appear
+struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    controllers: HashMap<u32, GameController>,
+    // the stick directions of each controller that are pushed past the deadzone
+    pushed: HashSet<(u32, Axis, bool)>
+}
+
+impl Gamepads {
+    fn add(&mut self, joystick_index: u32) {
+        match self.subsystem.open(joystick_index) {
+            Ok(controller) => {
+                println!("Opened game controller \"{}\".", controller.name());
+                self.controllers.insert(controller.instance_id(), controller);
+            }
+            Err(e) => println!("ERROR: Couldn't open game controller {}: {}", joystick_index, e)
+        }
+    }
+
+    fn remove(&mut self, instance_id: u32) {
+        if let Some(controller) = self.controllers.remove(&instance_id) {
+            println!("Closed game controller \"{}\".", controller.name());
+        }
+        self.pushed.retain(|&(id, ..)| id != instance_id);
+    }
+}
+
+fn hotkey_event(hotkey: Hotkey) -> InputEvent {
+    match hotkey {
+        Hotkey::Quit => InputEvent::Quit,
+        Hotkey::Pause => InputEvent::TogglePause,
+        Hotkey::Step => InputEvent::Step,
+        Hotkey::StepOver => InputEvent::StepOver,
+        Hotkey::Reset => InputEvent::Reset,
+        Hotkey::SaveState => InputEvent::SaveState(0),
+        Hotkey::LoadState => InputEvent::LoadState(0),
+        Hotkey::SpeedDown => InputEvent::Speed(false),
+        Hotkey::SpeedUp => InputEvent::Speed(true),
+        Hotkey::Rewind => InputEvent::Rewind(true),
+        Hotkey::RewindSlower => InputEvent::RewindSpeed(false),
+        Hotkey::RewindFaster => InputEvent::RewindSpeed(true)
+    }
+}
+
+// keyboard and gamepad events from the window and debugger commands typed into the terminal
+struct SdlInput {
+    event_pump: EventPump,
+    bindings: KeyBindings,
+    gamepads: Gamepads,
+    commands: Receiver<String>
+}
+
+impl InputSource for SdlInput {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+
+        for event in self.event_pump.poll_iter() {
+            let ctrl = Mod::LCTRLMOD | Mod::RCTRLMOD;
+            let alt = Mod::LALTMOD | Mod::RALTMOD;
+            match event {
+                Event::Quit {..} => events.push(InputEvent::Quit),
+                Event::KeyDown { keycode: Some(kc), keymod, .. } if keymod.intersects(ctrl) && STATE_SLOT_KEYS.contains(&kc) => {
+                    events.push(InputEvent::SaveState(STATE_SLOT_KEYS.iter().position(|&s| s == kc).unwrap()));
+                },
+                Event::KeyDown { keycode: Some(kc), keymod, .. } if keymod.intersects(alt) && STATE_SLOT_KEYS.contains(&kc) => {
+                    events.push(InputEvent::LoadState(STATE_SLOT_KEYS.iter().position(|&s| s == kc).unwrap()));
+                },
+                Event::KeyDown { keycode: Some(kc), repeat, .. } => {
+                    match self.bindings.hotkey(kc) {
+                        // holding the rewind key only counts once
+                        Some(Hotkey::Rewind) if repeat => {}
+                        Some(hotkey) => events.push(hotkey_event(hotkey)),
+                        None => {}
+                    }
+                    if let Some(&key) = self.bindings.keypad.get(&kc) {
+                        events.push(InputEvent::Key(Pressed(key)));
+                    }
+                },
+                Event::KeyUp { keycode: Some(kc), .. } => {
+                    if self.bindings.hotkey(kc) == Some(Hotkey::Rewind) {
+                        events.push(InputEvent::Rewind(false));
+                    }
+                    if let Some(&key) = self.bindings.keypad.get(&kc) {
+                        events.push(InputEvent::Key(Released(key)));
+                    }
+                },
+                // SDL2 also reports the controllers that were plugged in before it started
+                Event::ControllerDeviceAdded { which, .. } => self.gamepads.add(which),
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    self.gamepads.remove(which);
+                    events.extend(self.bindings.gamepad_keys().map(|&key| InputEvent::Key(Released(key))));
+                },
+                Event::ControllerButtonDown { button, .. } => {
+                    if let Some(&key) = self.bindings.buttons.get(&button) {
+                        events.push(InputEvent::Key(Pressed(key)));
+                    }
+                },
+                Event::ControllerButtonUp { button, .. } => {
+                    if let Some(&key) = self.bindings.buttons.get(&button) {
+                        events.push(InputEvent::Key(Released(key)));
+                    }
+                },
+                Event::ControllerAxisMotion { which, axis, value, .. } => {
+                    let position = value as f32 / i16::MAX as f32;
+                    for positive in [true, false] {
+                        let key = match self.bindings.axes.get(&(axis, positive)) {
+                            Some(&key) => key,
+                            None => continue
+                        };
+                        let pushed = if positive { position } else { -position } > self.bindings.deadzone;
+                        let direction = (which, axis, positive);
+                        if pushed && self.gamepads.pushed.insert(direction) {
+                            events.push(InputEvent::Key(Pressed(key)));
+                        } else if !pushed && self.gamepads.pushed.remove(&direction) {
+                            events.push(InputEvent::Key(Released(key)));
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        for line in self.commands.try_iter() {
+            match line.parse::<DebugCommand>() {
+                Ok(command) => events.push(InputEvent::Debug(command)),
+                Err(e) => println!("{}, {}", e, DebugCommand::HELP)
+            }
+        }
+        events
+    }
+}
+
+struct Options {
+    rom_path: String,
+    quirks: Option<Quirks>,
+    debug: bool,
+    seed: Option<u64>,
+    vip_rng: bool,
+    instructions_per_frame: Option<u32>,
+    record_path: Option<String>,
+    replay_path: Option<String>,
+    config_path: Option<String>,
+    database_path: Option<String>
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut quirks = None;
+    let mut debug = false;
+    let mut seed = None;
+    let mut vip_rng = false;
+    let mut instructions_per_frame = None;
+    let mut record_path = None;
+    let mut replay_path = None;
+    let mut config_path = None;
+    let mut database_path = None;
+
+    let mut arg_iter = args.iter().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        match arg.as_str() {
+            "--quirks" => {
+                let preset = arg_iter.next().ok_or("--quirks expects a preset name")?;
+                quirks = Some(preset.parse().map_err(|e| format!("{}", e))?);
+            }
+            "--debug" => debug = true,
+            "--seed" => {
+                let value = arg_iter.next().ok_or("--seed expects a number")?;
+                seed = Some(value.parse().map_err(|_| format!("invalid seed \"{}\"", value))?);
+            }
+            "--rng" => match arg_iter.next().map(String::as_str) {
+                Some("chacha") => vip_rng = false,
+                Some("vip") => vip_rng = true,
+                _ => return Err("--rng expects chacha or vip".to_string())
+            },
+            "--ipf" => {
+                let value = arg_iter.next().ok_or("--ipf expects a number")?;
+                instructions_per_frame = Some(value.parse().ok().filter(|&n| n > 0)
+                    .ok_or(format!("invalid instructions per frame \"{}\"", value))?);
+            }
+            "--record" => record_path = Some(arg_iter.next().ok_or("--record expects a file")?.clone()),
+            "--config" => config_path = Some(arg_iter.next().ok_or("--config expects a file")?.clone()),
+            "--romdb" => database_path = Some(arg_iter.next().ok_or("--romdb expects a file")?.clone()),
+            "--replay" => replay_path = Some(arg_iter.next().ok_or("--replay expects a file")?.clone()),
+            _ if rom_path.is_none() && !arg.starts_with("--") => rom_path = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument \"{}\"", arg))
+        }
+    }
+
+    let rom_path = rom_path.ok_or("missing rom path")?;
+    if record_path.is_some() && replay_path.is_some() {
+        return Err("--record and --replay can't be combined".to_string());
+    }
+    Ok(Options { rom_path, quirks, debug, seed, vip_rng, instructions_per_frame, record_path, replay_path, config_path, database_path })
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Invalid Syntax: {} <rom_path>", args[0]);
-        return;
-    }
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("Invalid Syntax ({}): {} [--quirks <{}>] [--debug] [--seed <n>] [--rng <chacha|vip>] [--ipf <n>] [--record <file> | --replay <file>] [--config <file>] [--romdb <file>] <rom_path>", e, args[0], Quirks::PRESET_NAMES.join("|"));
+            return;
+        }
+    };
 
-    let rom_data = fs::read(&args[1]);
+    let rom_data = fs::read(&options.rom_path);
     if rom_data.is_err() {
         println!("ERROR: Couldn't read ROM \"{}\"", rom_data.err().unwrap());
         return;
     }
 
     let rom_data = rom_data.unwrap();
-    if rom_data.len() > memory::PROGRAM_MEMORY_SIZE as usize {
+    let rom_hash = movie::rom_hash(&rom_data);
+
+    // like the config, the ROM databases only have to exist if one was asked for
+    let database_paths = match &options.database_path {
+        Some(path) => vec![PathBuf::from(path)],
+        None => RomDatabase::default_paths(Path::new(&options.rom_path)).into_iter().filter(|path| path.exists()).collect()
+    };
+    let mut database = RomDatabase::new();
+    for path in database_paths {
+        match RomDatabase::load(&path) {
+            Ok(loaded) => database.merge(loaded),
+            Err(e) => {
+                println!("ERROR: Couldn't load ROM database \"{}\": {}", path.display(), e);
+                return;
+            }
+        }
+    }
+
+    // what the database knows about the ROM only applies where the command line doesn't say otherwise
+    let rom_info = database.get(&rom_hash);
+    let quirks = options.quirks.or(rom_info.map(RomInfo::quirks)).unwrap_or_default();
+    let instructions_per_frame = options.instructions_per_frame
+        .or(rom_info.and_then(|info| info.instructions_per_frame))
+        .unwrap_or(timing::DEFAULT_INSTRUCTIONS_PER_FRAME);
+
+    let ram_size = romdb::ram_size(&rom_data, rom_info);
+    if rom_data.len() > ram_size - memory::RAM_INTPT_OFFSET {
         println!("ERROR: This file is too big for the chip8 RAM");
         return;
     }
 
-    let mut system = Chip8::default();
-    system.load_program(&rom_data[..]);
-    println!("Loaded ROM \"{}\" into memory. ({} bytes)", args[1], rom_data.len());
+    // a missing config file is only an error if it was asked for
+    let config = match options.config_path.as_ref().map(PathBuf::from).or_else(KeyConfig::default_path) {
+        Some(path) if options.config_path.is_some() || path.exists() => KeyConfig::load(&path, &rom_hash, rom_info)
+            .map_err(|e| format!("Couldn't load config \"{}\": {}", path.display(), e)),
+        _ => KeyConfig::parse("", &rom_hash, rom_info).map_err(|e| e.to_string())
+    };
+    let bindings = match config.and_then(|config| KeyBindings::resolve(&config)) {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            println!("ERROR: {}", e);
+            return;
+        }
+    };
+
+    // a movie sets the machine up the way it was recorded, recordings always get a seed
+    let rng = if options.vip_rng { RngKind::Vip } else { RngKind::ChaCha };
+    let movie = match (&options.replay_path, &options.record_path) {
+        (Some(path), _) => match fs::read(path).map_err(|e| e.to_string())
+            .and_then(|data| Movie::from_bytes(&data).map_err(|e| e.to_string())) {
+            Ok(movie) => Some(MovieMode::Replaying { movie, frame: 0 }),
+            Err(e) => {
+                println!("ERROR: Couldn't read movie \"{}\": {}", path, e);
+                return;
+            }
+        },
+        (None, Some(_)) => {
+            let seed = options.seed.unwrap_or_else(rand::random);
+            Some(MovieMode::Recording(Movie::new(&rom_data, ram_size, quirks, rng, seed, instructions_per_frame)))
+        }
+        (None, None) => None
+    };
+
+    let (system, instructions_per_frame) = match &movie {
+        Some(MovieMode::Recording(movie) | MovieMode::Replaying { movie, .. }) => match movie.create_system(&rom_data) {
+            Ok(system) => (system, movie.instructions_per_frame),
+            Err(e) => {
+                println!("ERROR: {}", e);
+                return;
+            }
+        },
+        None => {
+            let mut system = Chip8::with_ram_size(quirks, ram_size);
+            system.load_program(&rom_data[..]);
+            match (rng, options.seed) {
+                (RngKind::ChaCha, Some(seed)) => system.cpu.set_random_source(RandomSource::seeded(seed)),
+                (RngKind::Vip, seed) => system.cpu.set_random_source(RandomSource::vip(seed.unwrap_or_else(rand::random))),
+                (RngKind::ChaCha, None) => {}
+            }
+            (system, instructions_per_frame)
+        }
+    };
+    println!("Loaded ROM \"{}\" into memory. ({} bytes)", options.rom_path, rom_data.len());
+    if let Some(info) = rom_info {
+        println!("Recognized {}, a {} program.", info.full_title(), info.platform.name());
+        if let Some(description) = &info.description {
+            println!("{}", description);
+        }
+    }
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let audio_subsystem = sdl_context.audio().unwrap();
-    let mut event_pump = sdl_context.event_pump().unwrap();
+    let game_controller_subsystem = sdl_context.game_controller().unwrap();
+    let event_pump = sdl_context.event_pump().unwrap();
     println!("Initialized the SDL2 context and video subsystem.");
 
-    let rom_name = Path::new(&args[1]).file_name().unwrap();
-    let window = video_subsystem.window(format!("chip8-emulator: {:?}", rom_name).as_str(), SCREEN_WIDTH, SCREEN_HEIGHT)
+    let rom_name = match rom_info {
+        Some(info) => info.full_title(),
+        None => format!("{:?}", Path::new(&options.rom_path).file_name().unwrap())
+    };
+    let window = video_subsystem.window(format!("chip8-emulator: {}", rom_name).as_str(), SCREEN_WIDTH, SCREEN_HEIGHT)
         .position_centered()
         .build()
         .unwrap();
-    let mut canvas = window.into_canvas().build().unwrap();
+    let canvas = window.into_canvas().build().unwrap();
     println!("Created the SDL2 window.");
 
     let desired_spec = AudioSpecDesired {
@@ -88,95 +516,50 @@ fn main() {
         samples: None
     };
     let device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
-        SquareWave {
+        ToneGenerator {
             phase_inc: 440.0 / spec.freq as f32,
             phase: 0.0,
-            volume: 0.25
+            volume: 0.25,
+            freq: spec.freq as f32,
+            pattern: None,
+            pattern_inc: 0.0,
+            pattern_position: 0.0
         }
     }).unwrap();
-    let mut is_playing_tone = false;
     println!("Opened audio playback for tone generation.");
 
-    let mut cpu_coordinator = Coordinator::new(CYCLES_PER_SECOND);
-    let mut timer_coordinator = Coordinator::new(60);
-
-    'running: loop {
-        if cpu_coordinator.should_cycle() {
-            // pull keyboard events and pass them to the chip8 keyboard
-            let mut key_events = Vec::new();
-
-            for event in event_pump.poll_iter() {
-                match event {
-                    Event::Quit {..} |
-                    Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                        break 'running;
-                    },
-                    Event::KeyDown { keycode: Some(kc), .. } => {
-                        if KEYPAD_TABLE.contains(&kc) {
-                            let index = KEYPAD_TABLE.iter().position(|&s| s == kc);
-                            if let Some(index) = index {
-                                key_events.push(Pressed(index as u8));
-                            }
-                        }
-                    },
-                    Event::KeyUp { keycode: Some(kc), .. } => {
-                        if KEYPAD_TABLE.contains(&kc) {
-                            let index = KEYPAD_TABLE.iter().position(|&s| s == kc);
-                            if let Some(index) = index {
-                                key_events.push(Released(index as u8));
-                            }
-                        }
-                    },
-                    _ => {}
-                }
+    // debugger commands are typed into the terminal, next to the pause and step hotkeys
+    let (command_sender, command_receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if command_sender.send(line).is_err() {
+                break;
             }
-            system.keyboard.update_key_states(key_events);
-
-            // step the current instruction
-            system.step();
-
-            // if a rendering instruction was called, re-render the screen
-            if system.display.dirty {
-                canvas.set_draw_color(COLOR_CLEAR);
-                canvas.clear();
-
-                let display_data = system.display.borrow_display();
-
-                canvas.set_draw_color(COLOR_CONTRAST);
-                let rect_width = SCREEN_WIDTH / display::DISPLAY_WIDTH as u32;
-                let rect_height = SCREEN_HEIGHT / display::DISPLAY_HEIGHT as u32;
+        }
+    });
 
-                let mut rects = Vec::new();
-                for x in 0..display::DISPLAY_WIDTH as i32 {
-                    for y in 0..display::DISPLAY_HEIGHT as i32 {
-                        if display_data[x as usize][y as usize] {
-                            rects.push(Rect::new(x * rect_width as i32, y * rect_height as i32, rect_width, rect_height));
-                        }
-                    }
-                }
-                canvas.fill_rects(&rects[..]).ok();
-                canvas.present();
+    let mut video = SdlVideo { canvas };
+    let mut audio = SdlAudio { device };
+    let gamepads = Gamepads { subsystem: game_controller_subsystem, controllers: HashMap::new(), pushed: HashSet::new() };
+    let mut input = SdlInput { event_pump, bindings, gamepads, commands: command_receiver };
 
-                system.display.dirty = false;
-            }
+    let mut runner = Runner::new(system, instructions_per_frame);
+    runner.rom_path = Some(PathBuf::from(&options.rom_path));
+    runner.movie = movie;
+    if options.debug {
+        runner.debugger.pause();
+        println!("Started paused in the debugger, {}", DebugCommand::HELP);
+        runner.show_debugger(&mut video);
+    }
 
-            // play a tone if the cpu's sound timer is positive
-            if system.cpu.is_tone_on() {
-                if !is_playing_tone {
-                    device.resume();
-                }
-            } else if is_playing_tone {
-                device.pause();
-            }
-            is_playing_tone = system.cpu.is_tone_on();
-        }
+    if let Err(e) = runner.run(&mut video, &mut audio, &mut input) {
+        println!("ERROR: {}", e);
+    }
 
-        if timer_coordinator.should_cycle() {
-            // countdown the timers of the cpu
-            system.cpu.countdown_timers();
+    if let (Some(movie), Some(path)) = (runner.end_movie(&mut video), &options.record_path) {
+        match fs::write(path, movie.to_bytes()) {
+            Ok(_) => println!("Recorded {} frames to \"{}\".", movie.frames.len(), path),
+            Err(e) => println!("ERROR: Couldn't write movie \"{}\": {}", path, e)
         }
-
-        let coordinators = [&cpu_coordinator, &timer_coordinator];
-        thread::sleep(Duration::new(0, Coordinator::smallest_delay_until_cycle(&coordinators) as u32));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::{env, fs, process};
+use chip8::Chip8;
+use chip8::blocks::Backend;
+use chip8::keyboard::KeyEvent;
+use chip8::quirks::Quirks;
+use chip8::random::RandomSource;
+use chip8::romdb;
+use chip8::timing;
+
+// one character per XO-CHIP plane mask
+const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+const PNG_PIXELS: [u8; 4] = [0, 255, 170, 85];
+
+enum Limit {
+    Cycles(u64),
+    Frames(u64)
+}
+
+struct Options {
+    rom_path: String,
+    quirks: Quirks,
+    seed: Option<u64>,
+    vip_rng: bool,
+    backend: Backend,
+    limit: Limit,
+    instructions_per_frame: u32,
+    keys_path: Option<String>,
+    ascii: bool,
+    png_path: Option<String>,
+    json_path: Option<String>
+}
+
+const USAGE: &str = "<rom_path> [--cycles <n> | --frames <n>] [--ipf <n>] [--quirks <preset>] [--seed <n>] [--rng <chacha|vip>] [--backend <interpreter|blocks>] [--keys <file>] [--ascii] [--png <file>] [--json <file|->]";
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or(format!("{} expects a number", flag))?;
+    value.parse().map_err(|_| format!("invalid value \"{}\" for {}", value, flag))
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom_path: String::new(),
+        quirks: Quirks::default(),
+        seed: None,
+        vip_rng: false,
+        backend: Backend::Interpreter,
+        limit: Limit::Frames(60),
+        instructions_per_frame: timing::DEFAULT_INSTRUCTIONS_PER_FRAME,
+        keys_path: None,
+        ascii: false,
+        png_path: None,
+        json_path: None
+    };
+    let mut rom_path = None;
+
+    let mut arg_iter = args.iter().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        match arg.as_str() {
+            "--cycles" => options.limit = Limit::Cycles(parse_number(arg, arg_iter.next())?),
+            "--frames" => options.limit = Limit::Frames(parse_number(arg, arg_iter.next())?),
+            "--ipf" => options.instructions_per_frame = parse_number(arg, arg_iter.next())?,
+            "--quirks" => {
+                let preset = arg_iter.next().ok_or("--quirks expects a preset name")?;
+                options.quirks = preset.parse().map_err(|e| format!("{}", e))?;
+            }
+            "--seed" => options.seed = Some(parse_number(arg, arg_iter.next())?),
+            "--rng" => match arg_iter.next().map(String::as_str) {
+                Some("chacha") => options.vip_rng = false,
+                Some("vip") => options.vip_rng = true,
+                _ => return Err("--rng expects chacha or vip".to_string())
+            },
+            "--backend" => match arg_iter.next().map(String::as_str) {
+                Some("interpreter") => options.backend = Backend::Interpreter,
+                Some("blocks") => options.backend = Backend::Blocks,
+                _ => return Err("--backend expects interpreter or blocks".to_string())
+            },
+            "--keys" => options.keys_path = Some(arg_iter.next().ok_or("--keys expects a file")?.clone()),
+            "--ascii" => options.ascii = true,
+            "--png" => options.png_path = Some(arg_iter.next().ok_or("--png expects a file")?.clone()),
+            "--json" => options.json_path = Some(arg_iter.next().ok_or("--json expects a file")?.clone()),
+            _ if rom_path.is_none() && !arg.starts_with("--") => rom_path = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument \"{}\"", arg))
+        }
+    }
+
+    options.rom_path = rom_path.ok_or("missing rom path")?;
+    if options.instructions_per_frame == 0 {
+        return Err("--ipf must be positive".to_string());
+    }
+    // print the screen when no other output was asked for
+    if options.png_path.is_none() && options.json_path.is_none() {
+        options.ascii = true;
+    }
+    Ok(options)
+}
+
+// one event per line: `<frame> <down|up> <key>`, the key in hexadecimal, `#` starts a comment
+fn parse_key_script(script: &str) -> Result<BTreeMap<u64, Vec<KeyEvent>>, String> {
+    let mut events: BTreeMap<u64, Vec<KeyEvent>> = BTreeMap::new();
+    for (line_index, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {}", line_index + 1, message);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (frame, action, key) = match words[..] {
+            [frame, action, key] => (frame, action, key),
+            _ => return Err(error("expected <frame> <down|up> <key>"))
+        };
+        let frame = frame.parse().map_err(|_| error("invalid frame"))?;
+        let key = u8::from_str_radix(key, 16).ok().filter(|&k| k < 16).ok_or_else(|| error("invalid key"))?;
+        let event = match action {
+            "down" => KeyEvent::Pressed(key),
+            "up" => KeyEvent::Released(key),
+            _ => return Err(error("expected down or up"))
+        };
+        events.entry(frame).or_default().push(event);
+    }
+    Ok(events)
+}
+
+fn render_ascii(system: &Chip8) -> String {
+    let display = &system.display;
+    let pixels = display.borrow_display();
+    let mut out = String::new();
+    for y in 0..display.height() {
+        for column in pixels.iter().take(display.width()) {
+            out.push(ASCII_PIXELS[column[y] as usize]);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn write_png(system: &Chip8, path: &str) -> Result<(), String> {
+    let display = &system.display;
+    let pixels = display.borrow_display();
+    let mut data = Vec::with_capacity(display.width() * display.height());
+    for y in 0..display.height() {
+        for column in pixels.iter().take(display.width()) {
+            data.push(PNG_PIXELS[column[y] as usize]);
+        }
+    }
+
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), display.width() as u32, display.height() as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(&data).map_err(|e| e.to_string())
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn render_json(system: &Chip8, cycles: u64, frames: u64, error: Option<String>) -> String {
+    let cpu = &system.cpu;
+    let join = |values: Vec<String>| values.join(", ");
+    let v: Vec<String> = cpu.v_registers().iter().map(u8::to_string).collect();
+    let stack: Vec<String> = cpu.stack().iter().map(u16::to_string).collect();
+    let error = error.map(|e| json_string(&e)).unwrap_or_else(|| "null".to_string());
+    format!(concat!(
+        "{{\n",
+        "  \"cycles\": {},\n",
+        "  \"frames\": {},\n",
+        "  \"pc\": {},\n",
+        "  \"i\": {},\n",
+        "  \"v\": [{}],\n",
+        "  \"stack\": [{}],\n",
+        "  \"delay_timer\": {},\n",
+        "  \"sound_timer\": {},\n",
+        "  \"halted\": {},\n",
+        "  \"error\": {}\n",
+        "}}\n"),
+        cycles, frames, cpu.program_counter, cpu.i_register(), join(v), join(stack),
+        cpu.delay_timer(), cpu.sound_timer(), cpu.is_halted(), error)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("Invalid Syntax ({}): {} {}", e, args[0], USAGE);
+            process::exit(2);
+        }
+    };
+
+    let fail = |message: String| -> ! {
+        println!("ERROR: {}", message);
+        process::exit(2);
+    };
+
+    let rom_data = fs::read(&options.rom_path).unwrap_or_else(|e| fail(format!("Couldn't read ROM \"{}\"", e)));
+    let mut key_script = match &options.keys_path {
+        Some(path) => fs::read_to_string(path).map_err(|e| e.to_string())
+            .and_then(|script| parse_key_script(&script))
+            .unwrap_or_else(|e| fail(format!("Couldn't read key script \"{}\": {}", path, e))),
+        None => BTreeMap::new()
+    };
+
+    let mut system = Chip8::with_ram_size(options.quirks, romdb::ram_size(&rom_data, None));
+    if rom_data.len() > system.ram.program_memory_size() {
+        fail("This file is too big for the chip8 RAM".to_string());
+    }
+    system.load_program(&rom_data);
+    system.backend = options.backend;
+    match (options.vip_rng, options.seed) {
+        (false, Some(seed)) => system.cpu.set_random_source(RandomSource::seeded(seed)),
+        (true, seed) => system.cpu.set_random_source(RandomSource::vip(seed.unwrap_or_else(rand::random))),
+        (false, None) => {}
+    }
+
+    // a frame runs a fixed number of instructions, then the timers tick once
+    let mut cycles: u64 = 0;
+    let mut frames: u64 = 0;
+    let mut error = None;
+    'frames: loop {
+        if matches!(options.limit, Limit::Frames(limit) if frames >= limit) {
+            break;
+        }
+
+        // the scripted key events of a frame are applied before its first instruction
+        system.keyboard.update_key_states(key_script.remove(&frames).unwrap_or_default());
+
+        let budget = match options.limit {
+            Limit::Cycles(limit) => (limit - cycles).min(options.instructions_per_frame as u64) as u32,
+            Limit::Frames(_) => options.instructions_per_frame
+        };
+        let (executed, result) = system.run(budget);
+        cycles += executed as u64;
+        if let Err(e) = result {
+            error = Some(e.to_string());
+            break 'frames;
+        }
+        // a display wait ends the frame early, reaching the cycle limit or halting ends the run
+        let limit_reached = matches!(options.limit, Limit::Cycles(limit) if cycles >= limit);
+        if executed < options.instructions_per_frame && (limit_reached || system.cpu.is_halted()) {
+            break 'frames;
+        }
+        system.cpu.countdown_timers();
+        frames += 1;
+    }
+
+    if options.ascii {
+        print!("{}", render_ascii(&system));
+    }
+    if let Some(path) = &options.png_path {
+        write_png(&system, path).unwrap_or_else(|e| fail(format!("Couldn't write \"{}\": {}", path, e)));
+    }
+    if let Some(path) = &options.json_path {
+        let json = render_json(&system, cycles, frames, error.clone());
+        if path == "-" {
+            print!("{}", json);
+        } else {
+            fs::write(path, json).unwrap_or_else(|e| fail(format!("Couldn't write \"{}\": {}", path, e)));
+        }
+    }
+
+    if let Some(e) = error {
+        println!("ERROR: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,88 @@
+use std::{env, fs};
+use std::path::{Path, PathBuf};
+use chip8::movie::{self, hash_to_hex};
+use chip8::romdb::{self, RomDatabase};
+
+fn import(rom_dir: &Path, output_path: &Path) {
+    let existing = if output_path.exists() {
+        match RomDatabase::load(output_path) {
+            Ok(database) => database,
+            Err(e) => {
+                println!("ERROR: Couldn't load \"{}\": {}", output_path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        RomDatabase::new()
+    };
+
+    // entries that are already in the database may have been edited by hand, those are kept
+    let imported = match RomDatabase::import_dir(rom_dir) {
+        Ok(imported) => imported,
+        Err(e) => {
+            println!("ERROR: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let (known, imported_count) = (existing.len(), imported.len());
+    let mut database = imported;
+    database.merge(existing);
+
+    if let Err(e) = fs::write(output_path, database.to_toml()) {
+        println!("ERROR: Couldn't write \"{}\": {}", output_path.display(), e);
+        std::process::exit(1);
+    }
+    println!("Imported {} ROMs into \"{}\". ({} entries, {} already known)", imported_count, output_path.display(), database.len(), known);
+}
+
+fn info(rom_path: &Path, database_path: Option<&Path>) {
+    let rom_data = match fs::read(rom_path) {
+        Ok(rom_data) => rom_data,
+        Err(e) => {
+            println!("ERROR: Couldn't read ROM \"{}\"", e);
+            std::process::exit(1);
+        }
+    };
+    let hash = movie::rom_hash(&rom_data);
+    println!("SHA-1: {}", hash_to_hex(&hash));
+
+    let paths = match database_path {
+        Some(path) => vec![path.to_path_buf()],
+        None => RomDatabase::default_paths(rom_path)
+    };
+    let mut database = RomDatabase::new();
+    for path in paths.iter().filter(|path| database_path.is_some() || path.exists()) {
+        match RomDatabase::load(path) {
+            Ok(loaded) => database.merge(loaded),
+            Err(e) => println!("ERROR: Couldn't load \"{}\": {}", path.display(), e)
+        }
+    }
+
+    match database.get(&hash) {
+        Some(info) => {
+            println!("Title: {}", info.full_title());
+            println!("Platform: {}", info.platform.name());
+            if let Some(ipf) = info.instructions_per_frame {
+                println!("Instructions per frame: {}", ipf);
+            }
+            for (key, names) in &info.keys {
+                println!("Key {:X}: {}", key, names.join(", "));
+            }
+            if let Some(description) = &info.description {
+                println!("\n{}", description);
+            }
+        }
+        None => println!("Unknown ROM, guessed platform: {}", romdb::Platform::detect(&rom_data).name())
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    match &args[1..] {
+        [command, rom_dir] if command == "import" => import(Path::new(rom_dir), &Path::new(rom_dir).join(romdb::DATABASE_FILE_NAME)),
+        [command, rom_dir, flag, output] if command == "import" && flag == "-o" => import(Path::new(rom_dir), &PathBuf::from(output)),
+        [command, rom] if command == "info" => info(Path::new(rom), None),
+        [command, rom, flag, database] if command == "info" && flag == "--db" => info(Path::new(rom), Some(Path::new(database))),
+        _ => println!("Invalid Syntax: {0} import <rom_dir> [-o <database>] | {0} info <rom_path> [--db <database>]", args[0])
+    }
+}
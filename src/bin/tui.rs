@@ -0,0 +1,396 @@
+use std::io::{Stdout, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use std::{env, fs, io, thread};
+use crossterm::event::{Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags};
+use crossterm::event::{PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags};
+use crossterm::style::{Color, Colors, Print, ResetColor, SetColors};
+use crossterm::terminal::{Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, event, queue, terminal};
+use chip8::Chip8;
+use chip8::cpu::ExecutionError;
+use chip8::display::Display;
+use chip8::frontend::{AudioSink, InputEvent, InputSource, NullAudio, VideoSink};
+use chip8::keyboard::KeyEvent::{Pressed, Released};
+use chip8::quirks::Quirks;
+use chip8::random::RandomSource;
+use chip8::romdb;
+use chip8::runner::Runner;
+use chip8::timing;
+
+// the register panel is redrawn at most this often
+const PANEL_INTERVAL: Duration = Duration::from_millis(50);
+// without key release events a key counts as held until the terminal stops repeating it
+const KEY_HOLD: Duration = Duration::from_millis(200);
+
+// indexed by the XO-CHIP plane mask of a pixel
+const PLANE_COLORS: [Color; 4] = [
+    Color::Rgb { r: 0, g: 0, b: 0 },
+    Color::Rgb { r: 255, g: 255, b: 255 },
+    Color::Rgb { r: 170, g: 170, b: 170 },
+    Color::Rgb { r: 85, g: 85, b: 85 },
+];
+
+const KEYPAD_TABLE: [char; 16] = [
+    '1', '2', '3', '4',
+    'q', 'w', 'e', 'r',
+    'a', 's', 'd', 'f',
+    'z', 'x', 'c', 'v',
+];
+
+// bit of each dot of a braille character, indexed by [x][y] within the 2x4 cell
+const BRAILLE_DOTS: [[u8; 4]; 2] = [
+    [0x01, 0x02, 0x04, 0x40],
+    [0x08, 0x10, 0x20, 0x80],
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Glyphs {
+    // two pixels per character, keeps the XO-CHIP plane colors
+    HalfBlock,
+    // eight pixels per character, monochrome
+    Braille
+}
+
+impl Glyphs {
+    fn cell_size(self) -> (usize, usize) {
+        match self {
+            Glyphs::HalfBlock => (1, 2),
+            Glyphs::Braille => (2, 4)
+        }
+    }
+}
+
+struct TermVideo {
+    out: Stdout,
+    glyphs: Glyphs,
+    // the display size in characters, the panel is drawn to the right of it
+    screen_size: (u16, u16),
+    message: String,
+    debugger: String
+}
+
+impl TermVideo {
+    fn draw_screen(&mut self, display: &Display) -> io::Result<()> {
+        let pixels = display.borrow_display();
+        let (cell_width, cell_height) = self.glyphs.cell_size();
+        let columns = (display.width() / cell_width) as u16;
+        let rows = (display.height() / cell_height) as u16;
+        if (columns, rows) != self.screen_size {
+            // the resolution changed, the panel moves with the screen's edge
+            self.screen_size = (columns, rows);
+            queue!(self.out, Clear(ClearType::All))?;
+            self.draw_text()?;
+        }
+
+        for row in 0..rows as usize {
+            queue!(self.out, cursor::MoveTo(0, row as u16))?;
+            for column in 0..columns as usize {
+                let (x, y) = (column * cell_width, row * cell_height);
+                match self.glyphs {
+                    Glyphs::HalfBlock => {
+                        let colors = Colors::new(PLANE_COLORS[pixels[x][y] as usize], PLANE_COLORS[pixels[x][y + 1] as usize]);
+                        queue!(self.out, SetColors(colors), Print('▀'))?;
+                    }
+                    Glyphs::Braille => {
+                        let mut dots = 0;
+                        for (dx, column_dots) in BRAILLE_DOTS.iter().enumerate() {
+                            for (dy, dot) in column_dots.iter().enumerate() {
+                                if pixels[x + dx][y + dy] != 0 {
+                                    dots |= dot;
+                                }
+                            }
+                        }
+                        queue!(self.out, Print(char::from_u32(0x2800 + dots as u32).unwrap()))?;
+                    }
+                }
+            }
+        }
+        queue!(self.out, ResetColor)?;
+        self.out.flush()
+    }
+
+    fn draw_panel(&mut self, runner: &Runner) -> io::Result<()> {
+        let cpu = &runner.system.cpu;
+        let mut lines = vec![
+            format!("PC {:04X}  I {:04X}", cpu.program_counter, cpu.i_register()),
+            format!("DT {:02X}    ST {:02X}", cpu.delay_timer(), cpu.sound_timer()),
+        ];
+        for (index, values) in cpu.v_registers().chunks(2).enumerate() {
+            lines.push(format!("V{:X} {:02X}    V{:X} {:02X}", index * 2, values[0], index * 2 + 1, values[1]));
+        }
+        let stack: Vec<String> = cpu.stack().iter().map(|address| format!("{:03X}", address)).collect();
+        lines.push(format!("SP {:<2}   {}", stack.len(), stack.last().map(String::as_str).unwrap_or("")));
+        lines.push(if runner.debugger.is_paused() { "PAUSED".to_string() } else { "RUNNING".to_string() });
+
+        let column = self.screen_size.0 + 2;
+        for (row, line) in lines.iter().enumerate() {
+            queue!(self.out, cursor::MoveTo(column, row as u16), Print(format!("{:<20}", line)))?;
+        }
+        self.out.flush()
+    }
+
+    // the status message and the debugger's output go below the screen
+    fn draw_text(&mut self) -> io::Result<()> {
+        let row = self.screen_size.1 + 1;
+        queue!(self.out, cursor::MoveTo(0, row), Clear(ClearType::FromCursorDown), Print(&self.message))?;
+        for (index, line) in self.debugger.lines().enumerate() {
+            queue!(self.out, cursor::MoveTo(0, row + 2 + index as u16), Print(line))?;
+        }
+        self.out.flush()
+    }
+}
+
+// a frontend that can't draw can't do anything useful, terminal errors end the program
+impl VideoSink for TermVideo {
+    fn present(&mut self, display: &Display) {
+        self.draw_screen(display).expect("Couldn't draw to the terminal");
+    }
+
+    fn show_message(&mut self, message: &str) {
+        self.message = message.to_string();
+        self.draw_text().expect("Couldn't draw to the terminal");
+    }
+
+    fn show_debugger(&mut self, state: &str) {
+        self.debugger = state.to_string();
+        self.draw_text().expect("Couldn't draw to the terminal");
+    }
+}
+
+// rings the terminal bell whenever a tone starts
+struct TermBell;
+
+impl AudioSink for TermBell {
+    fn set_tone(&mut self, on: bool) {
+        if on {
+            let mut out = io::stdout();
+            out.write_all(b"\x07").and_then(|_| out.flush()).ok();
+        }
+    }
+}
+
+struct TermInput {
+    // whether the terminal reports key releases, most only report presses and repeats
+    reports_releases: bool,
+    held_since: [Option<Instant>; 16]
+}
+
+impl TermInput {
+    fn key_event(&mut self, events: &mut Vec<InputEvent>, key: usize, kind: KeyEventKind) {
+        match kind {
+            KeyEventKind::Press | KeyEventKind::Repeat => {
+                if self.held_since[key].is_none() {
+                    events.push(InputEvent::Key(Pressed(key as u8)));
+                }
+                self.held_since[key] = Some(Instant::now());
+            }
+            KeyEventKind::Release => {
+                self.held_since[key] = None;
+                events.push(InputEvent::Key(Released(key as u8)));
+            }
+        }
+    }
+}
+
+impl InputSource for TermInput {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            let key = match event::read() {
+                Ok(Event::Key(key)) => key,
+                _ => continue
+            };
+            let pressed = key.kind != KeyEventKind::Release;
+            match key.code {
+                KeyCode::Esc if pressed => events.push(InputEvent::Quit),
+                KeyCode::Char('c') if pressed && key.modifiers.contains(KeyModifiers::CONTROL) => events.push(InputEvent::Quit),
+                KeyCode::F(1) if pressed => events.push(InputEvent::TogglePause),
+                KeyCode::F(5) if pressed => events.push(InputEvent::SaveState(0)),
+                KeyCode::F(9) if pressed => events.push(InputEvent::LoadState(0)),
+                KeyCode::F(10) if pressed => events.push(InputEvent::StepOver),
+                KeyCode::F(11) if pressed => events.push(InputEvent::Step),
+                KeyCode::Char(c) => {
+                    if let Some(index) = KEYPAD_TABLE.iter().position(|&k| k == c.to_ascii_lowercase()) {
+                        self.key_event(&mut events, index, key.kind);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if !self.reports_releases {
+            for (key, held_since) in self.held_since.iter_mut().enumerate() {
+                if held_since.is_some_and(|since| since.elapsed() >= KEY_HOLD) {
+                    *held_since = None;
+                    events.push(InputEvent::Key(Released(key as u8)));
+                }
+            }
+        }
+        events
+    }
+}
+
+// puts the terminal into raw mode on the alternate screen, restoring it when dropped
+struct TerminalGuard {
+    reports_releases: bool
+}
+
+impl TerminalGuard {
+    fn enter() -> io::Result<TerminalGuard> {
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        queue!(out, EnterAlternateScreen, cursor::Hide, Clear(ClearType::All))?;
+        let reports_releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if reports_releases {
+            queue!(out, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+        }
+        out.flush()?;
+        Ok(TerminalGuard { reports_releases })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        if self.reports_releases {
+            queue!(out, PopKeyboardEnhancementFlags).ok();
+        }
+        queue!(out, ResetColor, cursor::Show, LeaveAlternateScreen).ok();
+        out.flush().ok();
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+struct Options {
+    rom_path: String,
+    quirks: Quirks,
+    debug: bool,
+    seed: Option<u64>,
+    vip_rng: bool,
+    instructions_per_frame: u32,
+    glyphs: Glyphs,
+    mute: bool
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        rom_path: String::new(),
+        quirks: Quirks::default(),
+        debug: false,
+        seed: None,
+        vip_rng: false,
+        instructions_per_frame: timing::DEFAULT_INSTRUCTIONS_PER_FRAME,
+        glyphs: Glyphs::HalfBlock,
+        mute: false
+    };
+    let mut rom_path = None;
+
+    let mut arg_iter = args.iter().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        match arg.as_str() {
+            "--quirks" => {
+                let preset = arg_iter.next().ok_or("--quirks expects a preset name")?;
+                options.quirks = preset.parse().map_err(|e| format!("{}", e))?;
+            }
+            "--debug" => options.debug = true,
+            "--seed" => {
+                let value = arg_iter.next().ok_or("--seed expects a number")?;
+                options.seed = Some(value.parse().map_err(|_| format!("invalid seed \"{}\"", value))?);
+            }
+            "--rng" => match arg_iter.next().map(String::as_str) {
+                Some("chacha") => options.vip_rng = false,
+                Some("vip") => options.vip_rng = true,
+                _ => return Err("--rng expects chacha or vip".to_string())
+            },
+            "--ipf" => {
+                let value = arg_iter.next().ok_or("--ipf expects a number")?;
+                options.instructions_per_frame = value.parse().ok().filter(|&n| n > 0)
+                    .ok_or(format!("invalid instructions per frame \"{}\"", value))?;
+            }
+            "--braille" => options.glyphs = Glyphs::Braille,
+            "--mute" => options.mute = true,
+            _ if rom_path.is_none() && !arg.starts_with("--") => rom_path = Some(arg.clone()),
+            _ => return Err(format!("unexpected argument \"{}\"", arg))
+        }
+    }
+
+    options.rom_path = rom_path.ok_or("missing rom path")?;
+    Ok(options)
+}
+
+fn run(runner: &mut Runner, options: &Options) -> io::Result<Result<(), ExecutionError>> {
+    let guard = TerminalGuard::enter()?;
+    let mut video = TermVideo {
+        out: io::stdout(),
+        glyphs: options.glyphs,
+        screen_size: (0, 0),
+        message: "Esc quits, F1 pauses, F10 steps over, F11 steps, F5/F9 save/load a state".to_string(),
+        debugger: String::new()
+    };
+    let mut bell = TermBell;
+    let mut silence = NullAudio;
+    let audio: &mut dyn AudioSink = if options.mute { &mut silence } else { &mut bell };
+    let mut input = TermInput { reports_releases: guard.reports_releases, held_since: [None; 16] };
+
+    video.draw_screen(&runner.system.display)?;
+    if options.debug {
+        runner.debugger.pause();
+        runner.show_debugger(&mut video);
+    }
+
+    let mut panel_drawn = Instant::now() - PANEL_INTERVAL;
+    loop {
+        match runner.tick(&mut video, audio, &mut input) {
+            Ok(true) => {}
+            Ok(false) => return Ok(Ok(())),
+            Err(e) => return Ok(Err(e))
+        }
+        if panel_drawn.elapsed() >= PANEL_INTERVAL {
+            video.draw_panel(runner)?;
+            panel_drawn = Instant::now();
+        }
+        thread::sleep(runner.clock.delay_until_frame());
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("Invalid Syntax ({}): {} [--quirks <{}>] [--debug] [--seed <n>] [--rng <chacha|vip>] [--ipf <n>] [--braille] [--mute] <rom_path>", e, args[0], Quirks::PRESET_NAMES.join("|"));
+            return;
+        }
+    };
+
+    let rom_data = match fs::read(&options.rom_path) {
+        Ok(rom_data) => rom_data,
+        Err(e) => {
+            println!("ERROR: Couldn't read ROM \"{}\"", e);
+            return;
+        }
+    };
+
+    let mut system = Chip8::with_ram_size(options.quirks, romdb::ram_size(&rom_data, None));
+    if rom_data.len() > system.ram.program_memory_size() {
+        println!("ERROR: This file is too big for the chip8 RAM");
+        return;
+    }
+
+    system.load_program(&rom_data);
+    match (options.vip_rng, options.seed) {
+        (false, Some(seed)) => system.cpu.set_random_source(RandomSource::seeded(seed)),
+        (true, seed) => system.cpu.set_random_source(RandomSource::vip(seed.unwrap_or_else(rand::random))),
+        (false, None) => {}
+    }
+
+    let mut runner = Runner::new(system, options.instructions_per_frame);
+    runner.rom_path = Some(PathBuf::from(&options.rom_path));
+    match run(&mut runner, &options) {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => println!("ERROR: {}", e),
+        Err(e) => println!("ERROR: Terminal failure \"{}\"", e)
+    }
+}
@@ -0,0 +1,159 @@
+use std::ops::Range;
+use crate::Chip8;
+use crate::cpu::ExecutionError;
+use crate::instructions::Instruction;
+use crate::memory::RAM;
+
+// longer straight runs of code are split into several blocks, which bounds how far before a
+// write a block covering it can start
+const MAX_BLOCK_LENGTH: usize = 32;
+const MAX_BLOCK_SIZE: usize = MAX_BLOCK_LENGTH * 4 + 4;
+
+// how `Chip8::run` executes instructions, both give the same results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    // decodes and executes one instruction at a time
+    #[default]
+    Interpreter,
+    // translates the code into blocks ahead of time, for long headless runs
+    Blocks
+}
+
+// an instruction translated for the block backend, the frequent two-byte ones that don't depend
+// on the quirks are run by `CPU::run_ops` directly, with the length of the instruction a skip
+// steps over worked out ahead of time
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Op {
+    LoadImmediate { x: u8, value: u8 },
+    AddImmediate { x: u8, value: u8 },
+    Move { x: u8, y: u8 },
+    Add { x: u8, y: u8 },
+    LoadI(u16),
+    AddI(u8),
+    ReadDelay(u8),
+    SetDelay(u8),
+    Jump(u16),
+    SkipIfEqualImmediate { x: u8, value: u8, skip: u16 },
+    SkipIfNotEqualImmediate { x: u8, value: u8, skip: u16 },
+    SkipIfEqual { x: u8, y: u8, skip: u16 },
+    SkipIfNotEqual { x: u8, y: u8, skip: u16 },
+    // everything else goes through `CPU::execute`
+    Execute(Instruction)
+}
+
+impl Op {
+    // `skip` is the length of the instruction after this one
+    fn translate(inst: Instruction, skip: u16) -> Op {
+        match inst {
+            Instruction::LD_RV(x, value) => Op::LoadImmediate { x, value },
+            Instruction::ADD_RV(x, value) => Op::AddImmediate { x, value },
+            Instruction::LD_RR(x, y) => Op::Move { x, y },
+            Instruction::ADD_RR(x, y) => Op::Add { x, y },
+            Instruction::LD_IV(n) => Op::LoadI(n),
+            Instruction::ADD_IR(x) => Op::AddI(x),
+            Instruction::LD_RD(x) => Op::ReadDelay(x),
+            Instruction::LD_DR(x) => Op::SetDelay(x),
+            Instruction::JP(n) => Op::Jump(n),
+            Instruction::SE_RV(x, value) => Op::SkipIfEqualImmediate { x, value, skip },
+            Instruction::SNE_RV(x, value) => Op::SkipIfNotEqualImmediate { x, value, skip },
+            Instruction::SE_RR(x, y) => Op::SkipIfEqual { x, y, skip },
+            Instruction::SNE_RR(x, y) => Op::SkipIfNotEqual { x, y, skip },
+            inst => Op::Execute(inst)
+        }
+    }
+}
+
+// whether execution can leave the straight line after the instruction, or memory the block was
+// read from can change under it
+fn ends_block(inst: &Instruction) -> bool {
+    matches!(inst,
+        Instruction::SYS(_) | Instruction::RET | Instruction::EXIT | Instruction::JP(_) | Instruction::CALL(_)
+        | Instruction::JP_RV(_) | Instruction::SE_RV(..) | Instruction::SNE_RV(..) | Instruction::SE_RR(..)
+        | Instruction::SNE_RR(..) | Instruction::SKP(_) | Instruction::SKNP(_) | Instruction::LD_RK(_)
+        | Instruction::DRW(..) | Instruction::LD_BR(_) | Instruction::LD_IRR(_) | Instruction::LD_IRXY(..))
+}
+
+// a run of instructions that always execute one after the other, the last one may jump, skip,
+// draw, wait or write to memory
+struct Block {
+    ops: Vec<Op>,
+    // the end of the bytes the block was translated from, including an instruction its skip steps over
+    end: usize
+}
+
+// blocks by the address they start at, translated the first time execution reaches them
+#[derive(Default)]
+pub(crate) struct BlockCache {
+    blocks: Vec<Option<Block>>
+}
+
+impl BlockCache {
+    // `None` when the instruction at `pc` can't be read or doesn't fit in memory
+    fn get_or_translate(&mut self, pc: usize, ram: &RAM) -> Option<&Block> {
+        if self.blocks.len() <= pc {
+            self.blocks.resize_with(pc + 1, || None);
+        }
+        if self.blocks[pc].is_none() {
+            self.blocks[pc] = translate(pc, ram);
+        }
+        self.blocks[pc].as_ref()
+    }
+
+    // drops the blocks translated from any of the bytes in `range`
+    fn invalidate(&mut self, range: Range<usize>) {
+        let end = range.end.min(self.blocks.len());
+        let start = range.start.saturating_sub(MAX_BLOCK_SIZE - 1).min(end);
+        for block in &mut self.blocks[start..end] {
+            if block.as_ref().is_some_and(|block| block.end > range.start) {
+                *block = None;
+            }
+        }
+    }
+}
+
+fn translate(pc: usize, ram: &RAM) -> Option<Block> {
+    let mut instructions = Vec::new();
+    let mut address = pc;
+    while instructions.len() < MAX_BLOCK_LENGTH && address + 2 <= ram.size() {
+        let Ok(inst) = ram.read_instruction(address) else { break };
+        if address + inst.length() as usize > ram.size() {
+            break;
+        }
+        instructions.push(inst);
+        address += inst.length() as usize;
+        if ends_block(&inst) {
+            break;
+        }
+    }
+    if instructions.is_empty() {
+        return None;
+    }
+
+    // like CPU::skip, an instruction that can't be read is skipped as two bytes
+    let skip = ram.read_instruction(address).map(|inst| inst.length()).unwrap_or(2);
+    let ops = instructions.into_iter().map(|inst| Op::translate(inst, skip)).collect();
+    Some(Block { ops, end: address + skip as usize })
+}
+
+// runs up to `instructions` instructions like `Chip8::step` would, returning how many completed
+pub(crate) fn run(system: &mut Chip8, instructions: u32) -> (u32, Result<(), ExecutionError>) {
+    let mut executed = 0;
+    while executed < instructions && !system.cpu.is_halted() && !system.cpu.is_waiting_for_vblank() {
+        if let Some(written) = system.ram.take_written() {
+            system.blocks.invalidate(written);
+        }
+        let pc = system.cpu.program_counter as usize;
+        let Some(block) = system.blocks.get_or_translate(pc, &system.ram) else {
+            // stepping reports why the instruction can't run
+            let result = system.step();
+            return (executed + result.is_ok() as u32, result);
+        };
+        let count = block.ops.len().min((instructions - executed) as usize);
+        let (completed, result) = system.cpu.run_ops(&mut system.ram, &system.keyboard, &mut system.display, &system.quirks, &block.ops[..count]);
+        executed += completed;
+        if result.is_err() {
+            return (executed, result);
+        }
+    }
+    (executed, Ok(()))
+}
@@ -0,0 +1,255 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+use serde::{Deserialize, Serialize};
+use crate::movie::{Hash, hash_to_hex};
+use crate::romdb::RomInfo;
+
+// host key names (as SDL2 names its keycodes) of the keypad keys 0-F
+pub const DEFAULT_KEYS: [&str; 16] = [
+    "1", "2", "3", "4",
+    "Q", "W", "E", "R",
+    "A", "S", "D", "F",
+    "Z", "X", "C", "V",
+];
+
+// gamepad buttons and stick directions (an SDL2 axis name followed by + or -) of the keypad keys,
+// the directions go to the keys most games move with
+pub const DEFAULT_BUTTONS: [(u8, &[&str]); 6] = [
+    (0x2, &["dpup", "lefty-"]),
+    (0x4, &["dpleft", "leftx-"]),
+    (0x6, &["dpright", "leftx+"]),
+    (0x8, &["dpdown", "lefty+"]),
+    (0x5, &["a"]),
+    (0x0, &["b"]),
+];
+// how far a stick has to be pushed, out of 1, before its direction counts as pressed
+pub const DEFAULT_DEADZONE: f32 = 0.4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Hotkey {
+    Quit,
+    Pause,
+    Step,
+    StepOver,
+    Reset,
+    SaveState,
+    LoadState,
+    SpeedDown,
+    SpeedUp,
+    Rewind,
+    RewindSlower,
+    RewindFaster
+}
+
+impl Hotkey {
+    pub const ALL: [Hotkey; 12] = [
+        Hotkey::Quit, Hotkey::Pause, Hotkey::Step, Hotkey::StepOver, Hotkey::Reset, Hotkey::SaveState,
+        Hotkey::LoadState, Hotkey::SpeedDown, Hotkey::SpeedUp, Hotkey::Rewind, Hotkey::RewindSlower, Hotkey::RewindFaster
+    ];
+
+    // the name used in the [hotkeys] table
+    pub fn name(self) -> &'static str {
+        match self {
+            Hotkey::Quit => "quit",
+            Hotkey::Pause => "pause",
+            Hotkey::Step => "step",
+            Hotkey::StepOver => "step_over",
+            Hotkey::Reset => "reset",
+            Hotkey::SaveState => "save_state",
+            Hotkey::LoadState => "load_state",
+            Hotkey::SpeedDown => "speed_down",
+            Hotkey::SpeedUp => "speed_up",
+            Hotkey::Rewind => "rewind",
+            Hotkey::RewindSlower => "rewind_slower",
+            Hotkey::RewindFaster => "rewind_faster"
+        }
+    }
+
+    fn default_key(self) -> &'static str {
+        match self {
+            Hotkey::Quit => "Escape",
+            Hotkey::Pause => "F1",
+            Hotkey::Step => "F11",
+            Hotkey::StepOver => "F10",
+            Hotkey::Reset => "F2",
+            Hotkey::SaveState => "F5",
+            Hotkey::LoadState => "F9",
+            Hotkey::SpeedDown => "F3",
+            Hotkey::SpeedUp => "F4",
+            Hotkey::Rewind => "Backspace",
+            Hotkey::RewindSlower => "-",
+            Hotkey::RewindFaster => "="
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError(String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// a keypad key can be bound to one name or a list of them
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Names {
+    One(String),
+    Many(Vec<String>)
+}
+
+impl Names {
+    pub(crate) fn into_vec(self) -> Vec<String> {
+        match self {
+            Names::One(name) if name.is_empty() => Vec::new(),
+            Names::One(name) => vec![name],
+            Names::Many(names) => names
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct BindingsTable {
+    keys: BTreeMap<String, Names>,
+    buttons: BTreeMap<String, Names>,
+    hotkeys: BTreeMap<String, String>,
+    deadzone: Option<f32>
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    keys: BTreeMap<String, Names>,
+    buttons: BTreeMap<String, Names>,
+    hotkeys: BTreeMap<String, String>,
+    deadzone: Option<f32>,
+    // per-ROM tables keyed by the ROM's SHA-1, applied over the global one
+    roms: BTreeMap<String, BindingsTable>
+}
+
+// the host keys and gamepad buttons, by name, that drive the keypad and the emulator's hotkeys,
+// resolving the names is up to the frontend
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyConfig {
+    pub keys: BTreeMap<u8, Vec<String>>,
+    pub buttons: BTreeMap<u8, Vec<String>>,
+    pub hotkeys: BTreeMap<Hotkey, String>,
+    pub deadzone: f32
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        KeyConfig {
+            keys: DEFAULT_KEYS.iter().enumerate().map(|(key, name)| (key as u8, vec![name.to_string()])).collect(),
+            buttons: DEFAULT_BUTTONS.iter().map(|(key, names)| (*key, names.iter().map(|name| name.to_string()).collect())).collect(),
+            hotkeys: Hotkey::ALL.iter().map(|&hotkey| (hotkey, hotkey.default_key().to_string())).collect(),
+            deadzone: DEFAULT_DEADZONE
+        }
+    }
+}
+
+// an empty name unbinds a key
+fn bind<K: Ord, V: Default + PartialEq>(bindings: &mut BTreeMap<K, V>, key: K, value: V) {
+    if value == V::default() {
+        bindings.remove(&key);
+    } else {
+        bindings.insert(key, value);
+    }
+}
+
+// names are compared ignoring case, like SDL2 looks them up
+fn claim(bound: &mut BTreeMap<String, String>, name: &str, what: String) -> Result<(), ConfigError> {
+    match bound.insert(name.to_lowercase(), what.clone()) {
+        Some(first) => Err(ConfigError(format!("\"{}\" is bound to both {} and {}", name, first, what))),
+        None => Ok(())
+    }
+}
+
+pub(crate) fn parse_keypad_key(key: &str) -> Result<u8, ConfigError> {
+    u8::from_str_radix(key, 16).ok().filter(|&k| k < 16 && key.len() == 1)
+        .ok_or_else(|| ConfigError(format!("\"{}\" isn't a keypad key, expected 0-F", key)))
+}
+
+// $XDG_CONFIG_HOME/chip8, falling back to ~/.config/chip8
+pub fn config_dir() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("chip8"))
+}
+
+impl KeyConfig {
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    // the default bindings, changed by the config's global tables, then by the keys the ROM database
+    // has for the ROM and finally by the config's tables for the ROM
+    pub fn parse(source: &str, rom_hash: &Hash, rom: Option<&RomInfo>) -> Result<KeyConfig, ConfigError> {
+        let file: ConfigFile = toml::from_str(source).map_err(|e| ConfigError(e.to_string().trim_end().to_string()))?;
+        let mut config = KeyConfig::default();
+        let mut roms = file.roms;
+        config.apply(BindingsTable { keys: file.keys, buttons: file.buttons, hotkeys: file.hotkeys, deadzone: file.deadzone })?;
+        for (&key, names) in rom.iter().flat_map(|rom| &rom.keys) {
+            bind(&mut config.keys, key, names.clone());
+        }
+        if let Some(rom) = roms.remove(&hash_to_hex(rom_hash)) {
+            config.apply(rom)?;
+        }
+        config.check_duplicates()?;
+        Ok(config)
+    }
+
+    pub fn load(path: &Path, rom_hash: &Hash, rom: Option<&RomInfo>) -> Result<KeyConfig, ConfigError> {
+        let source = fs::read_to_string(path).map_err(|e| ConfigError(e.to_string()))?;
+        KeyConfig::parse(&source, rom_hash, rom)
+    }
+
+    fn apply(&mut self, table: BindingsTable) -> Result<(), ConfigError> {
+        for (key, names) in table.keys {
+            bind(&mut self.keys, parse_keypad_key(&key)?, names.into_vec());
+        }
+        for (key, names) in table.buttons {
+            bind(&mut self.buttons, parse_keypad_key(&key)?, names.into_vec());
+        }
+        for (hotkey, name) in table.hotkeys {
+            let hotkey = Hotkey::ALL.iter().find(|h| h.name() == hotkey)
+                .ok_or_else(|| ConfigError(format!("unknown hotkey \"{}\"", hotkey)))?;
+            bind(&mut self.hotkeys, *hotkey, name);
+        }
+        if let Some(deadzone) = table.deadzone {
+            if !(0.0..1.0).contains(&deadzone) {
+                return Err(ConfigError(format!("the deadzone must be between 0 and 1, not {}", deadzone)));
+            }
+            self.deadzone = deadzone;
+        }
+        Ok(())
+    }
+
+    // a host key can only do one thing and a button can only drive one keypad key
+    fn check_duplicates(&self) -> Result<(), ConfigError> {
+        let mut keys = BTreeMap::new();
+        for (key, names) in &self.keys {
+            for name in names {
+                claim(&mut keys, name, format!("keypad key {:X}", key))?;
+            }
+        }
+        for (hotkey, name) in &self.hotkeys {
+            claim(&mut keys, name, format!("hotkey {}", hotkey.name()))?;
+        }
+        let mut buttons = BTreeMap::new();
+        for (key, names) in &self.buttons {
+            for name in names {
+                claim(&mut buttons, name, format!("keypad key {:X}", key))?;
+            }
+        }
+        Ok(())
+    }
+}
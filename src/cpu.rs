@@ -1,10 +1,54 @@
-use std::cmp::max;
-use std::time::SystemTime;
-use rand::Rng;
-use rand::rngs::ThreadRng;
+use std::fmt;
+use std::fmt::Formatter;
+use crate::blocks::Op;
 use crate::instructions::Instruction;
+use crate::quirks::{IndexIncrement, Quirks};
+use crate::random::RandomSource;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
 use crate::{Keyboard, RAM, Display, memory};
 
+pub const STACK_SIZE: usize = 16;
+pub const RPL_FLAG_COUNT: usize = 16;
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+pub const DEFAULT_PITCH: u8 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionError {
+    StackUnderflow { address: u16 },
+    StackOverflow { address: u16 },
+    MemoryOutOfRange { address: usize, length: usize },
+    UnsupportedSys { address: u16, target: u16 },
+    InvalidInstruction { address: u16, opcode: u16 }
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionError::StackUnderflow { address }
+                => write!(f, "stack underflow on RET at {:#06x}", address),
+            ExecutionError::StackOverflow { address }
+                => write!(f, "stack overflow on CALL at {:#06x} (more than {} entries)", address, STACK_SIZE),
+            ExecutionError::MemoryOutOfRange { address, length }
+                => write!(f, "memory access out of range: {} byte(s) at {:#06x}", length, address),
+            ExecutionError::UnsupportedSys { address, target }
+                => write!(f, "unsupported SYS {:#05x} at {:#06x}", target, address),
+            ExecutionError::InvalidInstruction { address, opcode }
+                => write!(f, "couldn't read instruction {:#06x} at {:#06x}", opcode, address)
+        }
+    }
+}
+
+impl std::error::Error for ExecutionError {}
+
+// how far a FX0A key wait got, it completes once the key is released (or pressed, see Quirks)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyWait {
+    // waiting for a key to go down in a keyboard update after the `since`th one
+    Press { since: u32 },
+    // waiting for the key to go back up
+    Release(u8)
+}
+
 #[derive(Debug)]
 pub struct CPU {
     v_reg: [u8; 16],
@@ -13,7 +57,14 @@ pub struct CPU {
     sound_timer: u8,
     pub program_counter: u16,
     stack: Vec<u16>,
-    rng: ThreadRng
+    rpl_flags: [u8; RPL_FLAG_COUNT],
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    pitch: u8,
+    halted: bool,
+    // set by DRW under the display wait quirk, cleared by the next timer tick
+    waiting_for_vblank: bool,
+    key_wait: Option<KeyWait>,
+    rng: RandomSource
 }
 
 impl Default for CPU {
@@ -24,47 +75,100 @@ impl Default for CPU {
             delay_timer: 0,
             sound_timer: 0,
             program_counter: 0,
-            stack: Vec::with_capacity(16),
-            rng: rand::thread_rng()
+            stack: Vec::with_capacity(STACK_SIZE),
+            rpl_flags: [0; RPL_FLAG_COUNT],
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            halted: false,
+            waiting_for_vblank: false,
+            key_wait: None,
+            rng: RandomSource::default()
         }
     }
 }
 
 impl CPU {
-    pub fn execute(&mut self, ram: &mut RAM, keyboard: &Keyboard, display: &mut Display, instruction: Instruction) {
-        self.program_counter += 2;
+    pub fn execute(&mut self, ram: &mut RAM, keyboard: &Keyboard, display: &mut Display, quirks: &Quirks, instruction: Instruction) -> Result<(), ExecutionError> {
+        let address = self.program_counter;
+        self.program_counter = self.program_counter.wrapping_add(instruction.length());
 
         match instruction {
-            Instruction::SYS(_)
-                => panic!("SYS instruction not supported"),
+            Instruction::SYS(n) => {
+                self.program_counter = address;
+                return Err(ExecutionError::UnsupportedSys { address, target: n });
+            }
+            Instruction::SCD(n) => {
+                display.scroll_down(n as usize);
+            }
             Instruction::CLS => {
                 display.clear();
             }
             Instruction::RET => {
-                self.program_counter = self.stack.pop().unwrap();
+                match self.stack.pop() {
+                    Some(return_address) => self.program_counter = return_address,
+                    None => {
+                        self.program_counter = address;
+                        return Err(ExecutionError::StackUnderflow { address });
+                    }
+                }
+            }
+            Instruction::SCR => {
+                display.scroll_right(4);
+            }
+            Instruction::SCL => {
+                display.scroll_left(4);
+            }
+            Instruction::EXIT => {
+                self.halted = true;
+            }
+            Instruction::LOW => {
+                display.set_hires(false);
+            }
+            Instruction::HIGH => {
+                display.set_hires(true);
             }
             Instruction::JP(n) => {
-                let n = n;
                 self.program_counter = n;
             }
             Instruction::CALL(n) => {
-                let n = n;
+                if self.stack.len() >= STACK_SIZE {
+                    self.program_counter = address;
+                    return Err(ExecutionError::StackOverflow { address });
+                }
                 self.stack.push(self.program_counter);
                 self.program_counter = n;
             }
             Instruction::SE_RV(r, v) => {
                 if self.v_reg[r as usize] == v {
-                    self.program_counter += 2;
+                    self.skip(ram);
                 }
             }
             Instruction::SNE_RV(r, v) => {
                 if self.v_reg[r as usize] != v {
-                    self.program_counter += 2;
+                    self.skip(ram);
                 }
             }
             Instruction::SE_RR(r1, r2) => {
                 if self.v_reg[r1 as usize] == self.v_reg[r2 as usize] {
-                    self.program_counter += 2;
+                    self.skip(ram);
+                }
+            }
+            Instruction::LD_IRXY(r1, r2) => {
+                let (low, high) = (r1.min(r2) as usize, r1.max(r2) as usize);
+                let store_address = self.checked_i_range(ram, address, high - low + 1)?;
+                let memory = ram.borrow_memory_range_mut(store_address, high - low + 1);
+                memory.copy_from_slice(&self.v_reg[low..=high]);
+                if r1 > r2 {
+                    memory.reverse();
+                }
+            }
+            Instruction::LD_RXYI(r1, r2) => {
+                let (low, high) = (r1.min(r2) as usize, r1.max(r2) as usize);
+                let load_address = self.checked_i_range(ram, address, high - low + 1)?;
+                let memory = ram.borrow_memory_range(load_address, high - low + 1);
+                self.v_reg[low..=high].copy_from_slice(memory);
+                if r1 > r2 {
+                    self.v_reg[low..=high].reverse();
                 }
             }
             Instruction::LD_RV(r, v) => {
@@ -78,12 +182,21 @@ impl CPU {
             }
             Instruction::OR(r1, r2) => {
                 self.v_reg[r1 as usize] |= self.v_reg[r2 as usize];
+                if quirks.logic_resets_vf {
+                    self.v_reg[0xF] = 0;
+                }
             }
             Instruction::AND(r1, r2) => {
                 self.v_reg[r1 as usize] &= self.v_reg[r2 as usize];
+                if quirks.logic_resets_vf {
+                    self.v_reg[0xF] = 0;
+                }
             }
             Instruction::XOR(r1, r2) => {
                 self.v_reg[r1 as usize] ^= self.v_reg[r2 as usize];
+                if quirks.logic_resets_vf {
+                    self.v_reg[0xF] = 0;
+                }
             }
             Instruction::ADD_RR(r1, r2) => {
                 let (v, cf) = self.v_reg[r1 as usize].overflowing_add(self.v_reg[r2 as usize]);
@@ -95,57 +208,88 @@ impl CPU {
                 self.v_reg[r1 as usize] = v;
                 self.v_reg[0xF] = (!bf).into();
             }
-            Instruction::SHR(r1, _) => {
-                let v = self.v_reg[r1 as usize];
+            Instruction::SHR(r1, r2) => {
+                let v = self.v_reg[if quirks.shift_uses_vy { r2 } else { r1 } as usize];
+                self.v_reg[r1 as usize] = v >> 1;
                 self.v_reg[0xF] = v & 0b1;
-                self.v_reg[r1 as usize] = v / 2;
             }
             Instruction::SUBN(r1, r2) => {
                 let (v, bf) = self.v_reg[r2 as usize].overflowing_sub(self.v_reg[r1 as usize]);
                 self.v_reg[r1 as usize] = v;
                 self.v_reg[0xF] = (!bf).into();
             }
-            Instruction::SHL(r1, _) => {
-                let v = self.v_reg[r1 as usize];
-                self.v_reg[0xF] = v & 0b1000;
-                self.v_reg[r1 as usize] = v * 2;
+            Instruction::SHL(r1, r2) => {
+                let v = self.v_reg[if quirks.shift_uses_vy { r2 } else { r1 } as usize];
+                self.v_reg[r1 as usize] = v << 1;
+                self.v_reg[0xF] = v >> 7;
             }
             Instruction::SNE_RR(r1, r2) => {
                 if self.v_reg[r1 as usize] != self.v_reg[r2 as usize] {
-                    self.program_counter += 2;
+                    self.skip(ram);
                 }
             }
             Instruction::LD_IV(n) => {
                 self.i_reg = n;
             }
+            Instruction::LD_IL(n) => {
+                self.i_reg = n;
+            }
             Instruction::JP_RV(n) => {
-                self.program_counter = n - self.v_reg[0] as u16;
+                let r = if quirks.jump_uses_vx { (n >> 8) & 0xF } else { 0 };
+                self.program_counter = n + self.v_reg[r as usize] as u16;
             }
             Instruction::RND(r, v) => {
-                self.v_reg[r as usize] = self.rng.gen::<u8>() & v;
+                self.v_reg[r as usize] = self.rng.next_byte(ram) & v;
             }
+            Instruction::DRW(x, y, 0) => {
+                let length = 32 * display.selected_plane_count();
+                let sprite_address = self.checked_i_range(ram, address, length)?;
+                let sprite_data = ram.borrow_memory_range(sprite_address, length);
+                self.v_reg[0xF] = display.draw_large_sprite(self.v_reg[x as usize], self.v_reg[y as usize], sprite_data, quirks.wrap_sprites).into();
+                self.waiting_for_vblank = quirks.display_wait;
+            },
             Instruction::DRW(x, y, n) => {
-                let sprite_data = ram.borrow_memory_range(self.i_reg as usize, n as usize);
-                self.v_reg[0xF] = display.draw_sprite(self.v_reg[x as usize], self.v_reg[y as usize], sprite_data).into();
+                let length = n as usize * display.selected_plane_count();
+                let sprite_address = self.checked_i_range(ram, address, length)?;
+                let sprite_data = ram.borrow_memory_range(sprite_address, length);
+                self.v_reg[0xF] = display.draw_sprite(self.v_reg[x as usize], self.v_reg[y as usize], sprite_data, quirks.wrap_sprites).into();
+                self.waiting_for_vblank = quirks.display_wait;
             },
             Instruction::SKP(r) => {
-                if keyboard.is_pressed(self.v_reg[r as usize] as usize) {
-                    self.program_counter += 2;
+                if keyboard.is_pressed((self.v_reg[r as usize] & 0xF) as usize) {
+                    self.skip(ram);
                 }
             }
             Instruction::SKNP(r) => {
-                if !keyboard.is_pressed(self.v_reg[r as usize] as usize) {
-                    self.program_counter += 2;
+                if !keyboard.is_pressed((self.v_reg[r as usize] & 0xF) as usize) {
+                    self.skip(ram);
                 }
             }
             Instruction::LD_RD(r) => {
                 self.v_reg[r as usize] = self.delay_timer;
             }
             Instruction::LD_RK(r) => {
-                if keyboard.last_pressed.is_some() {
-                    self.v_reg[r as usize] = keyboard.last_pressed.unwrap();
-                } else {
-                    self.program_counter -= 2;
+                // executed over and over until a key went down and, with the quirk, back up
+                let key = match self.key_wait {
+                    None => {
+                        self.key_wait = Some(KeyWait::Press { since: keyboard.updates() });
+                        None
+                    }
+                    Some(KeyWait::Press { since }) => match keyboard.first_pressed().filter(|_| keyboard.updates() != since) {
+                        Some(key) if quirks.key_wait_release => {
+                            self.key_wait = Some(KeyWait::Release(key));
+                            None
+                        }
+                        key => key
+                    },
+                    Some(KeyWait::Release(key)) => (!keyboard.is_pressed(key as usize)).then_some(key)
+                };
+                match key {
+                    Some(key) => {
+                        self.v_reg[r as usize] = key;
+                        self.key_wait = None;
+                    }
+                    None => self.program_counter = address
                 }
             },
             Instruction::LD_DR(r) => {
@@ -155,30 +299,239 @@ impl CPU {
                 self.sound_timer = self.v_reg[r as usize];
             }
             Instruction::ADD_IR(r) => {
-                self.i_reg += self.v_reg[r as usize] as u16;
+                self.i_reg = self.i_reg.wrapping_add(self.v_reg[r as usize] as u16);
             }
             Instruction::LD_RF(r) => {
-                self.i_reg = (self.v_reg[r as usize] as usize * memory::INTPT_SPRITE_LENGTH) as u16;
+                let digit = (self.v_reg[r as usize] & 0xF) as usize;
+                self.i_reg = (digit * memory::INTPT_SPRITE_LENGTH) as u16;
+            },
+            Instruction::LD_RHF(r) => {
+                let digit = (self.v_reg[r as usize] & 0xF) as usize;
+                self.i_reg = (memory::INTPT_HIRES_SPRITE_OFFSET + digit * memory::INTPT_HIRES_SPRITE_LENGTH) as u16;
             },
             Instruction::LD_BR(r) => {
                 let val = self.v_reg[r as usize];
-                let memory = ram.borrow_memory_range_mut(self.i_reg as usize, 3);
+                let bcd_address = self.checked_i_range(ram, address, 3)?;
+                let memory = ram.borrow_memory_range_mut(bcd_address, 3);
                 memory[0] = val / 100;
                 memory[1] = val % 100 / 10;
                 memory[2] = val % 10;
             },
             Instruction::LD_IRR(tr) => {
-                let memory = ram.borrow_memory_range_mut(self.i_reg as usize, (tr + 1) as usize);
+                let store_address = self.checked_i_range(ram, address, (tr + 1) as usize)?;
+                let memory = ram.borrow_memory_range_mut(store_address, (tr + 1) as usize);
                 memory[..=tr as usize].copy_from_slice(&self.v_reg[..=tr as usize]);
+                self.increment_i_after_transfer(tr, quirks);
             }
             Instruction::LD_RRI(tr) => {
-                let memory = ram.borrow_memory_range(self.i_reg as usize, (tr + 1) as usize);
+                let load_address = self.checked_i_range(ram, address, (tr + 1) as usize)?;
+                let memory = ram.borrow_memory_range(load_address, (tr + 1) as usize);
                 self.v_reg[..=tr as usize].copy_from_slice(&memory[..=tr as usize]);
+                self.increment_i_after_transfer(tr, quirks);
+            }
+            Instruction::LD_PRR(tr) => {
+                self.rpl_flags[..=tr as usize].copy_from_slice(&self.v_reg[..=tr as usize]);
+            }
+            Instruction::LD_RRP(tr) => {
+                self.v_reg[..=tr as usize].copy_from_slice(&self.rpl_flags[..=tr as usize]);
+            }
+            Instruction::PLANE(n) => {
+                display.select_planes(n);
+            }
+            Instruction::AUDIO => {
+                let pattern_address = self.checked_i_range(ram, address, AUDIO_PATTERN_SIZE)?;
+                let mut pattern = [0; AUDIO_PATTERN_SIZE];
+                pattern.copy_from_slice(ram.borrow_memory_range(pattern_address, AUDIO_PATTERN_SIZE));
+                self.audio_pattern = Some(pattern);
+            }
+            Instruction::PITCH(r) => {
+                self.pitch = self.v_reg[r as usize];
+            }
+        }
+
+        Ok(())
+    }
+
+    // runs the ops of a translated block in order, stopping at the first fault, and returns how
+    // many of them completed
+    pub(crate) fn run_ops(&mut self, ram: &mut RAM, keyboard: &Keyboard, display: &mut Display, quirks: &Quirks, ops: &[Op]) -> (u32, Result<(), ExecutionError>) {
+        for (executed, &op) in ops.iter().enumerate() {
+            let mut next = self.program_counter.wrapping_add(2);
+            match op {
+                Op::LoadImmediate { x, value } => self.v_reg[x as usize] = value,
+                Op::AddImmediate { x, value } => self.v_reg[x as usize] = self.v_reg[x as usize].wrapping_add(value),
+                Op::Move { x, y } => self.v_reg[x as usize] = self.v_reg[y as usize],
+                Op::Add { x, y } => {
+                    let (v, cf) = self.v_reg[x as usize].overflowing_add(self.v_reg[y as usize]);
+                    self.v_reg[x as usize] = v;
+                    self.v_reg[0xF] = cf.into();
+                }
+                Op::LoadI(n) => self.i_reg = n,
+                Op::AddI(x) => self.i_reg = self.i_reg.wrapping_add(self.v_reg[x as usize] as u16),
+                Op::ReadDelay(x) => self.v_reg[x as usize] = self.delay_timer,
+                Op::SetDelay(x) => self.delay_timer = self.v_reg[x as usize],
+                Op::Jump(n) => next = n,
+                Op::SkipIfEqualImmediate { x, value, skip } => if self.v_reg[x as usize] == value {
+                    next = next.wrapping_add(skip);
+                },
+                Op::SkipIfNotEqualImmediate { x, value, skip } => if self.v_reg[x as usize] != value {
+                    next = next.wrapping_add(skip);
+                },
+                Op::SkipIfEqual { x, y, skip } => if self.v_reg[x as usize] == self.v_reg[y as usize] {
+                    next = next.wrapping_add(skip);
+                },
+                Op::SkipIfNotEqual { x, y, skip } => if self.v_reg[x as usize] != self.v_reg[y as usize] {
+                    next = next.wrapping_add(skip);
+                },
+                Op::Execute(inst) => {
+                    if let Err(e) = self.execute(ram, keyboard, display, quirks, inst) {
+                        return (executed as u32, Err(e));
+                    }
+                    continue;
+                }
+            }
+            self.program_counter = next;
+        }
+        (ops.len() as u32, Ok(()))
+    }
+
+    // skips the next instruction, which is four bytes long if it's an XO-CHIP long load
+    fn skip(&mut self, ram: &mut RAM) {
+        let next = ram.decode_instruction(self.program_counter as usize);
+        let length = next.map(|inst| inst.length()).unwrap_or(2);
+        self.program_counter = self.program_counter.wrapping_add(length);
+    }
+
+    fn increment_i_after_transfer(&mut self, tr: u8, quirks: &Quirks) {
+        match quirks.index_increment {
+            IndexIncrement::Unchanged => {}
+            IndexIncrement::ByX => self.i_reg = self.i_reg.wrapping_add(tr as u16),
+            IndexIncrement::ByXPlusOne => self.i_reg = self.i_reg.wrapping_add(tr as u16 + 1)
+        }
+    }
+
+    // validates that `length` bytes starting at I fit in RAM, rewinding the program counter
+    // to the faulting instruction if they don't
+    fn checked_i_range(&mut self, ram: &RAM, address: u16, length: usize) -> Result<usize, ExecutionError> {
+        let start = self.i_reg as usize;
+        if start + length > ram.size() {
+            self.program_counter = address;
+            return Err(ExecutionError::MemoryOutOfRange { address: start, length });
+        }
+        Ok(start)
+    }
+
+    // replaces the generator used by RND, e.g. with a seeded one for reproducible runs
+    pub fn set_random_source(&mut self, rng: RandomSource) {
+        self.rng = rng;
+    }
+
+    pub fn random_source(&self) -> &RandomSource {
+        &self.rng
+    }
+
+    pub fn v_registers(&self) -> &[u8; 16] {
+        &self.v_reg
+    }
+
+    pub fn i_register(&self) -> u16 {
+        self.i_reg
+    }
+
+    // return addresses, innermost call last
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub(crate) fn write_state(&self, out: &mut StateWriter) {
+        out.write_bytes(&self.v_reg);
+        out.write_u16(self.i_reg);
+        out.write_u8(self.delay_timer);
+        out.write_u8(self.sound_timer);
+        out.write_u16(self.program_counter);
+        out.write_u8(self.stack.len() as u8);
+        for &address in &self.stack {
+            out.write_u16(address);
+        }
+        out.write_bytes(&self.rpl_flags);
+        out.write_bool(self.audio_pattern.is_some());
+        out.write_bytes(&self.audio_pattern.unwrap_or([0; AUDIO_PATTERN_SIZE]));
+        out.write_u8(self.pitch);
+        out.write_bool(self.halted);
+        out.write_bool(self.waiting_for_vblank);
+        match self.key_wait {
+            None => out.write_u8(0),
+            Some(KeyWait::Press { since }) => {
+                out.write_u8(1);
+                out.write_u32(since);
+            }
+            Some(KeyWait::Release(key)) => {
+                out.write_u8(2);
+                out.write_u8(key);
             }
         }
+        self.rng.write_state(out);
+    }
+
+    pub(crate) fn read_state(input: &mut StateReader) -> Result<CPU, SaveStateError> {
+        let v_reg = input.read_array()?;
+        let i_reg = input.read_u16()?;
+        let delay_timer = input.read_u8()?;
+        let sound_timer = input.read_u8()?;
+        let program_counter = input.read_u16()?;
+        let stack_length = input.read_u8()? as usize;
+        if stack_length > STACK_SIZE {
+            return Err(SaveStateError::Invalid("stack size"));
+        }
+        let mut stack = Vec::with_capacity(STACK_SIZE);
+        for _ in 0..stack_length {
+            stack.push(input.read_u16()?);
+        }
+        let rpl_flags = input.read_array()?;
+        let has_audio_pattern = input.read_bool()?;
+        let audio_pattern = input.read_array()?;
+        let pitch = input.read_u8()?;
+        let halted = input.read_bool()?;
+        let waiting_for_vblank = input.read_bool()?;
+        let key_wait = match input.read_u8()? {
+            0 => None,
+            1 => Some(KeyWait::Press { since: input.read_u32()? }),
+            2 => match input.read_u8()? {
+                key if key < 16 => Some(KeyWait::Release(key)),
+                _ => return Err(SaveStateError::Invalid("key"))
+            },
+            _ => return Err(SaveStateError::Invalid("key wait"))
+        };
+        let rng = RandomSource::read_state(input)?;
+
+        Ok(CPU {
+            v_reg,
+            i_reg,
+            delay_timer,
+            sound_timer,
+            program_counter,
+            stack,
+            rpl_flags,
+            audio_pattern: if has_audio_pattern { Some(audio_pattern) } else { None },
+            pitch,
+            halted,
+            waiting_for_vblank,
+            key_wait,
+            rng
+        })
     }
 
+    // called once per 60Hz frame, which is also when a display wait ends
     pub fn countdown_timers(&mut self) {
+        self.waiting_for_vblank = false;
         if self.sound_timer > 0 {
             self.sound_timer -= 1;
         }
@@ -187,45 +540,39 @@ impl CPU {
         }
     }
 
+    // like the VIP, the tone also plays while FX0A waits for a key to be released
     pub fn is_tone_on(&self) -> bool {
-        self.sound_timer > 0
+        self.sound_timer > 0 || matches!(self.key_wait, Some(KeyWait::Release(_)))
     }
-}
 
-pub struct Coordinator {
-    pub rate: u32,
-    offset: u128,
-    last_execution: u128
-}
+    // the 128-bit pattern loaded by the program through F002, if any
+    pub fn audio_pattern(&self) -> Option<&[u8; AUDIO_PATTERN_SIZE]> {
+        self.audio_pattern.as_ref()
+    }
 
-impl Coordinator {
-    pub fn new(rate: u32) -> Self {
-        Coordinator {
-            rate,
-            offset: 1_000_000_000 / rate as u128,
-            last_execution: 0
-        }
+    pub fn pitch(&self) -> u8 {
+        self.pitch
     }
 
-    pub fn should_cycle(&mut self) -> bool {
-        let duration_since_epoch = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
-        let timestamp_nanos = duration_since_epoch.as_nanos();
-        if timestamp_nanos - self.last_execution >= self.offset {
-            self.last_execution = timestamp_nanos;
-            true
-        } else {
-            false
-        }
+    // rate at which the bits of the audio pattern are played, in bits per second
+    pub fn audio_playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
     }
 
-    pub fn delay_until_cycle(&self) -> u128 {
-        let duration_since_epoch = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
-        let timestamp_nanos = duration_since_epoch.as_nanos();
-        max((self.last_execution + self.offset) as i128 - timestamp_nanos as i128, 0) as u128
+    // set once the program executes EXIT (00FD)
+    pub fn is_halted(&self) -> bool {
+        self.halted
     }
 
-    pub fn smallest_delay_until_cycle(coordinators: &[&Coordinator]) -> u128 {
-        coordinators.iter().map(|coordinator| coordinator.delay_until_cycle())
-            .min().unwrap()
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
     }
-}
\ No newline at end of file
+
+    pub fn borrow_rpl_flags(&self) -> &[u8; RPL_FLAG_COUNT] {
+        &self.rpl_flags
+    }
+
+    pub fn borrow_rpl_flags_mut(&mut self) -> &mut [u8; RPL_FLAG_COUNT] {
+        &mut self.rpl_flags
+    }
+}
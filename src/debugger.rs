@@ -0,0 +1,185 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::{Formatter, Write};
+use std::str::FromStr;
+use crate::Chip8;
+use crate::instructions::Instruction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugCommand {
+    Continue,
+    Pause,
+    Step,
+    StepOver,
+    RunTo(u16),
+    AddBreakpoint(u16),
+    RemoveBreakpoint(u16),
+    Print
+}
+
+pub struct DebugCommandParseError(String);
+
+impl fmt::Debug for DebugCommandParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for DebugCommandParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for DebugCommandParseError {}
+
+impl DebugCommand {
+    pub const HELP: &'static str = "commands: c(ontinue), p(ause), s(tep), n(ext), r(un) <addr>, b(reak) <addr>, d(elete) <addr>, i(nfo)";
+}
+
+fn parse_address(arg: Option<&str>) -> Result<u16, DebugCommandParseError> {
+    let arg = arg.ok_or_else(|| DebugCommandParseError("missing address".to_string()))?;
+    let digits = arg.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| DebugCommandParseError(format!("invalid address \"{}\"", arg)))
+}
+
+impl FromStr for DebugCommand {
+    type Err = DebugCommandParseError;
+
+    // addresses are always read as hexadecimal, with or without a 0x prefix
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let argument = words.next();
+        match command {
+            "c" | "continue" => Ok(DebugCommand::Continue),
+            "p" | "pause" => Ok(DebugCommand::Pause),
+            "s" | "step" => Ok(DebugCommand::Step),
+            "n" | "next" => Ok(DebugCommand::StepOver),
+            "r" | "run" => Ok(DebugCommand::RunTo(parse_address(argument)?)),
+            "b" | "break" => Ok(DebugCommand::AddBreakpoint(parse_address(argument)?)),
+            "d" | "delete" => Ok(DebugCommand::RemoveBreakpoint(parse_address(argument)?)),
+            "i" | "info" => Ok(DebugCommand::Print),
+            _ => Err(DebugCommandParseError(format!("unknown command \"{}\"", command)))
+        }
+    }
+}
+
+// stops execution at breakpoints, once the target of a step over / run to is reached
+// or after a single step
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    paused: bool,
+    single_step: bool,
+    // address to stop at and the deepest call stack it may be reached with
+    run_target: Option<(u16, usize)>
+}
+
+impl Debugger {
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.single_step = false;
+        self.run_target = None;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn step(&mut self) {
+        self.paused = false;
+        self.single_step = true;
+    }
+
+    // runs a CALL until it returns, any other instruction is single stepped
+    pub fn step_over(&mut self, system: &Chip8) {
+        let pc = system.cpu.program_counter;
+        match system.ram.read_instruction(pc as usize) {
+            Ok(Instruction::CALL(_)) => {
+                self.run_target = Some((pc.wrapping_add(2), system.cpu.stack().len()));
+                self.paused = false;
+            }
+            _ => self.step()
+        }
+    }
+
+    pub fn run_to(&mut self, address: u16) {
+        self.run_target = Some((address, usize::MAX));
+        self.paused = false;
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item=&u16> {
+        self.breakpoints.iter()
+    }
+
+    // applies a command, returns whether the machine state should be shown afterwards
+    pub fn apply(&mut self, command: DebugCommand, system: &Chip8) -> bool {
+        match command {
+            DebugCommand::Continue => self.resume(),
+            DebugCommand::Pause => self.pause(),
+            DebugCommand::Step => self.step(),
+            DebugCommand::StepOver => self.step_over(system),
+            DebugCommand::RunTo(address) => self.run_to(address),
+            DebugCommand::AddBreakpoint(address) => self.add_breakpoint(address),
+            DebugCommand::RemoveBreakpoint(address) => self.remove_breakpoint(address),
+            DebugCommand::Print => return true
+        }
+        self.paused
+    }
+
+    // to be called after every executed instruction, returns true if execution just stopped
+    pub fn check(&mut self, system: &Chip8) -> bool {
+        let pc = system.cpu.program_counter;
+        let reached_target = match self.run_target {
+            Some((address, depth)) => pc == address && system.cpu.stack().len() <= depth,
+            None => false
+        };
+        if self.single_step || reached_target || self.breakpoints.contains(&pc) {
+            self.pause();
+            return true;
+        }
+        false
+    }
+
+    // registers, timers, call stack and the instructions surrounding the program counter
+    pub fn describe(&self, system: &Chip8, window: u16) -> String {
+        let cpu = &system.cpu;
+        let mut out = String::new();
+
+        for (i, v) in cpu.v_registers().iter().enumerate() {
+            write!(out, "V{:X}={:02x}{}", i, v, if i % 8 == 7 { "\n" } else { " " }).unwrap();
+        }
+        writeln!(out, "I={:#06x} PC={:#06x} DT={:02x} ST={:02x}", cpu.i_register(), cpu.program_counter, cpu.delay_timer(), cpu.sound_timer()).unwrap();
+        let stack: Vec<String> = cpu.stack().iter().map(|address| format!("{:#06x}", address)).collect();
+        writeln!(out, "stack: [{}]", stack.join(", ")).unwrap();
+
+        let pc = cpu.program_counter;
+        let start = pc.saturating_sub(window * 2);
+        for address in (start..pc.saturating_add(window * 2 + 1)).step_by(2) {
+            if address as usize + 2 > system.ram.size() {
+                break;
+            }
+            let marker = if address == pc { ">" } else if self.breakpoints.contains(&address) { "*" } else { " " };
+            let opcode = system.ram.borrow_memory_range(address as usize, 2);
+            let opcode = (opcode[0] as u16) << 8 | opcode[1] as u16;
+            match system.ram.read_instruction(address as usize) {
+                Ok(inst) => writeln!(out, "{} {:#06x}: {:04x}  {}", marker, address, opcode, inst).unwrap(),
+                Err(_) => writeln!(out, "{} {:#06x}: {:04x}  ???", marker, address, opcode).unwrap()
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Formatter;
+use crate::instructions::Instruction;
+
+// number of bytes listed on a single `db` line
+const DATA_BYTES_PER_LINE: usize = 8;
+
+pub enum ListingItem {
+    Instruction(Instruction),
+    Data
+}
+
+pub struct ListingLine {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub label: Option<String>,
+    pub item: ListingItem
+}
+
+// a disassembled program, its `Display` output can be fed back to the assembler
+pub struct Listing {
+    pub lines: Vec<ListingLine>,
+    labels: BTreeMap<u16, String>
+}
+
+impl Listing {
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    fn format_item(&self, line: &ListingLine) -> String {
+        match &line.item {
+            ListingItem::Instruction(inst) => match inst.address_operand().and_then(|address| self.label(address)) {
+                Some(label) => inst.format_with_address(label),
+                None => inst.to_string()
+            },
+            ListingItem::Data => {
+                let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:#04X}", b)).collect();
+                format!("db {}", bytes.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            if let Some(label) = &line.label {
+                writeln!(f, "{}:", label)?;
+            }
+            let raw: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            writeln!(f, "    {:<40} ; {:#06X}  {}", self.format_item(line), line.address, raw.join(""))?;
+        }
+        Ok(())
+    }
+}
+
+fn read_at(program: &[u8], origin: u16, address: u16) -> Option<Instruction> {
+    let offset = address.checked_sub(origin)? as usize;
+    let word = |offset: usize| -> Option<u16> {
+        let bytes = program.get(offset..offset+2)?;
+        Some((bytes[0] as u16) << 8 | bytes[1] as u16)
+    };
+    let inst = Instruction::read_long(word(offset)?, word(offset + 2).unwrap_or(0)).ok()?;
+    if offset + inst.length() as usize > program.len() {
+        return None;
+    }
+    Some(inst)
+}
+
+// follows every path the program can take from `origin`, returning the addresses of the reachable instructions
+fn trace_code(program: &[u8], origin: u16) -> BTreeSet<u16> {
+    let mut code = BTreeSet::new();
+    let mut pending = vec![origin];
+
+    while let Some(address) = pending.pop() {
+        if code.contains(&address) {
+            continue;
+        }
+        let inst = match read_at(program, origin, address) {
+            Some(inst) => inst,
+            None => continue
+        };
+        code.insert(address);
+
+        let next = address.wrapping_add(inst.length());
+        match inst {
+            Instruction::JP(n) => pending.push(n),
+            Instruction::CALL(n) => {
+                pending.push(n);
+                pending.push(next);
+            }
+            Instruction::SE_RV(..) | Instruction::SNE_RV(..) | Instruction::SE_RR(..) |
+            Instruction::SNE_RR(..) | Instruction::SKP(_) | Instruction::SKNP(_) => {
+                pending.push(next);
+                let skipped_length = read_at(program, origin, next).map(|inst| inst.length()).unwrap_or(2);
+                pending.push(next.wrapping_add(skipped_length));
+            }
+            // computed jumps can't be followed, SYS isn't supported by the interpreter
+            Instruction::RET | Instruction::EXIT | Instruction::JP_RV(_) | Instruction::SYS(_) => {}
+            _ => pending.push(next)
+        }
+    }
+    code
+}
+
+// disassembles a program loaded at `origin`, labelling JP/CALL targets (`L_xxxx`) and the
+// addresses I is loaded with (`D_xxxx`), anything that isn't reachable code is listed as data
+pub fn disassemble(program: &[u8], origin: u16) -> Listing {
+    let code = trace_code(program, origin);
+    let end = origin as usize + program.len();
+    let in_program = |address: u16| (origin as usize..end).contains(&(address as usize));
+
+    let mut labels = BTreeMap::new();
+    for &address in &code {
+        let inst = read_at(program, origin, address).unwrap();
+        match inst {
+            Instruction::JP(n) | Instruction::CALL(n) if in_program(n) => {
+                labels.insert(n, format!("L_{:04X}", n));
+            }
+            Instruction::LD_IV(n) | Instruction::LD_IL(n) if in_program(n) => {
+                labels.entry(n).or_insert_with(|| format!("D_{:04X}", n));
+            }
+            _ => {}
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut address = origin as usize;
+    while address < end {
+        let label = labels.get(&(address as u16)).cloned();
+        let offset = address - origin as usize;
+
+        // an instruction is only listed as such if no label points inside of it
+        let inst = read_at(program, origin, address as u16)
+            .filter(|_| code.contains(&(address as u16)))
+            .filter(|inst| (1..inst.length() as usize).all(|i| !labels.contains_key(&((address + i) as u16))));
+        if let Some(inst) = inst {
+            let length = inst.length() as usize;
+            lines.push(ListingLine {
+                address: address as u16,
+                bytes: program[offset..offset+length].to_vec(),
+                label,
+                item: ListingItem::Instruction(inst)
+            });
+            address += length;
+            continue;
+        }
+
+        let mut data_end = address + 1;
+        while data_end < end && data_end - address < DATA_BYTES_PER_LINE
+            && !code.contains(&(data_end as u16)) && !labels.contains_key(&(data_end as u16)) {
+            data_end += 1;
+        }
+        lines.push(ListingLine {
+            address: address as u16,
+            bytes: program[offset..data_end - origin as usize].to_vec(),
+            label,
+            item: ListingItem::Data
+        });
+        address = data_end;
+    }
+
+    Listing { lines, labels }
+}
@@ -1,54 +1,213 @@
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
 pub const DISPLAY_WIDTH: usize = 64;
 pub const DISPLAY_HEIGHT: usize = 32;
+pub const HIRES_DISPLAY_WIDTH: usize = 128;
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+pub const PLANE_COUNT: usize = 2;
+
+// every pixel is a bitmask of the planes it is lit on, bit 0 being the first plane
+pub type Framebuffer = [[u8; HIRES_DISPLAY_HEIGHT]; HIRES_DISPLAY_WIDTH];
 
 pub struct Display {
-    pixel_states: [[bool; DISPLAY_HEIGHT]; DISPLAY_WIDTH],
+    pixel_states: Framebuffer,
+    hires: bool,
+    selected_planes: u8,
     pub dirty: bool
 }
 
 impl Default for Display {
     fn default() -> Self {
         Display {
-            pixel_states: [[false; DISPLAY_HEIGHT]; DISPLAY_WIDTH],
+            pixel_states: [[0; HIRES_DISPLAY_HEIGHT]; HIRES_DISPLAY_WIDTH],
+            hires: false,
+            selected_planes: 0b01,
             dirty: false
         }
     }
 }
 
 impl Display {
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_DISPLAY_WIDTH } else { DISPLAY_WIDTH }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_DISPLAY_HEIGHT } else { DISPLAY_HEIGHT }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // switching resolutions always clears every plane
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        for pixel in self.pixel_states.iter_mut().flatten() {
+            *pixel = 0;
+        }
+        self.dirty = true;
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    // `planes` is the XO-CHIP plane mask, 0 selects no plane at all
+    pub fn select_planes(&mut self, planes: u8) {
+        self.selected_planes = planes & ((1 << PLANE_COUNT) - 1);
+    }
+
+    pub fn selected_plane_count(&self) -> usize {
+        self.selected_planes.count_ones() as usize
+    }
+
+    pub fn is_pixel_on(&self, x: usize, y: usize) -> bool {
+        self.pixel_states[x][y] != 0
+    }
+
+    // only clears the selected planes
     pub fn clear(&mut self) {
         for pixel in self.pixel_states.iter_mut().flatten() {
-            *pixel = false;
+            *pixel &= !self.selected_planes;
+        }
+        self.dirty = true;
+    }
+
+    // the starting position always wraps around the screen, `wrap` decides whether the
+    // parts of the sprite that go past an edge wrap as well or are clipped
+    //
+    // `sprite_data` holds one sprite per selected plane, one after the other
+    pub fn draw_sprite(&mut self, x: u8, y: u8, sprite_data: &[u8], wrap: bool) -> bool {
+        let sprite_length = sprite_data.len() / self.selected_plane_count().max(1);
+        self.draw_planes(sprite_data, sprite_length, |display, plane, sprite| {
+            let rows = sprite.iter().map(|&row| (row as u16) << 8);
+            display.draw_rows(x, y, plane, rows, 8, wrap)
+        })
+    }
+
+    // draws a 16x16 SUPER-CHIP sprite, stored as 16 rows of two bytes
+    pub fn draw_large_sprite(&mut self, x: u8, y: u8, sprite_data: &[u8], wrap: bool) -> bool {
+        self.draw_planes(sprite_data, 32, |display, plane, sprite| {
+            let rows = sprite.chunks(2).map(|row| (row[0] as u16) << 8 | row[1] as u16);
+            display.draw_rows(x, y, plane, rows, 16, wrap)
+        })
+    }
+
+    fn draw_planes<F>(&mut self, sprite_data: &[u8], sprite_length: usize, mut draw: F) -> bool
+        where F: FnMut(&mut Display, u8, &[u8]) -> bool {
+        let mut collision = false;
+        let mut sprites = sprite_data.chunks(sprite_length.max(1));
+        for plane in 0..PLANE_COUNT {
+            let plane = 1 << plane;
+            if self.selected_planes & plane == 0 {
+                continue;
+            }
+            if let Some(sprite) = sprites.next() {
+                collision |= draw(self, plane, sprite);
+            }
         }
         self.dirty = true;
+        collision
     }
 
-    pub fn draw_sprite(&mut self, x: u8, y: u8, sprite_data: &[u8]) -> bool {
-        let x = x as i16;
-        let y = y as i16;
+    fn draw_rows(&mut self, x: u8, y: u8, plane: u8, rows: impl Iterator<Item=u16>, sprite_width: usize, wrap: bool) -> bool {
+        let (width, height) = (self.width(), self.height());
+        let x = x as usize % width;
+        let y = y as usize % height;
 
         let mut collision = false;
-        for py in y..y+sprite_data.len() as i16 {
-            let mut sprite_row = sprite_data[(py-y) as usize];
-            for px in x..x+8 {
-                let px_wrapped = px as usize % DISPLAY_WIDTH;
-                let py_wrapped = py as usize % DISPLAY_HEIGHT;
-                let display_pixel = &mut self.pixel_states[px_wrapped][py_wrapped];
-                let sprite_pixel = sprite_row & 128 == 128;
-
-                if *display_pixel && sprite_pixel {
+        for (row_index, mut sprite_row) in rows.enumerate() {
+            let py = y + row_index;
+            for px in x..x+sprite_width {
+                let sprite_pixel = sprite_row & 0x8000 == 0x8000;
+                sprite_row <<= 1;
+
+                if !sprite_pixel || (!wrap && (px >= width || py >= height)) {
+                    continue;
+                }
+                let display_pixel = &mut self.pixel_states[px % width][py % height];
+
+                if *display_pixel & plane != 0 {
                     collision = true;
                 }
-                *display_pixel ^= sprite_pixel;
+                *display_pixel ^= plane;
+            }
+        }
+        collision
+    }
 
-                sprite_row <<= 1;
+    pub fn scroll_down(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = n.min(height);
+        let planes = self.selected_planes;
+        for column in self.pixel_states.iter_mut().take(width) {
+            for y in (0..height).rev() {
+                let shifted = if y >= n { column[y - n] } else { 0 };
+                column[y] = (column[y] & !planes) | (shifted & planes);
             }
         }
         self.dirty = true;
-        collision
     }
 
-    pub fn borrow_display(&self) -> &[[bool; DISPLAY_HEIGHT]; DISPLAY_WIDTH] {
+    pub fn scroll_right(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = n.min(width);
+        let planes = self.selected_planes;
+        for x in (0..width).rev() {
+            for y in 0..height {
+                let shifted = if x >= n { self.pixel_states[x - n][y] } else { 0 };
+                let pixel = &mut self.pixel_states[x][y];
+                *pixel = (*pixel & !planes) | (shifted & planes);
+            }
+        }
+        self.dirty = true;
+    }
+
+    pub fn scroll_left(&mut self, n: usize) {
+        let (width, height) = (self.width(), self.height());
+        let n = n.min(width);
+        let planes = self.selected_planes;
+        for x in 0..width {
+            for y in 0..height {
+                let shifted = if x + n < width { self.pixel_states[x + n][y] } else { 0 };
+                let pixel = &mut self.pixel_states[x][y];
+                *pixel = (*pixel & !planes) | (shifted & planes);
+            }
+        }
+        self.dirty = true;
+    }
+
+    pub(crate) fn write_state(&self, out: &mut StateWriter) {
+        out.write_bool(self.hires);
+        out.write_u8(self.selected_planes);
+        for column in &self.pixel_states {
+            out.write_bytes(column);
+        }
+    }
+
+    // the restored display is always marked dirty so it gets redrawn
+    pub(crate) fn read_state(input: &mut StateReader) -> Result<Display, SaveStateError> {
+        let mut display = Display {
+            hires: input.read_bool()?,
+            selected_planes: input.read_u8()?,
+            ..Display::default()
+        };
+        if display.selected_planes >= 1 << PLANE_COUNT {
+            return Err(SaveStateError::Invalid("plane selection"));
+        }
+        for column in display.pixel_states.iter_mut() {
+            column.copy_from_slice(input.read_bytes(HIRES_DISPLAY_HEIGHT)?);
+            if column.iter().any(|&pixel| pixel >= 1 << PLANE_COUNT) {
+                return Err(SaveStateError::Invalid("pixel"));
+            }
+        }
+        display.dirty = true;
+        Ok(display)
+    }
+
+    // only the top-left `width()` x `height()` pixels are part of the current screen
+    pub fn borrow_display(&self) -> &Framebuffer {
         &self.pixel_states
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,58 @@
+use crate::cpu::AUDIO_PATTERN_SIZE;
+use crate::debugger::DebugCommand;
+use crate::display::Display;
+use crate::keyboard::KeyEvent;
+
+// everything a frontend can ask of the Runner
+pub enum InputEvent {
+    Key(KeyEvent),
+    Quit,
+    TogglePause,
+    Step,
+    StepOver,
+    Debug(DebugCommand),
+    SaveState(usize),
+    LoadState(usize),
+    // sent when the rewind key goes down and up again
+    Rewind(bool),
+    // makes rewinding go faster or slower
+    RewindSpeed(bool),
+    // puts the machine back the way it started
+    Reset,
+    // makes the emulation go faster or slower
+    Speed(bool)
+}
+
+pub trait VideoSink {
+    // called whenever the display changed
+    fn present(&mut self, display: &Display);
+
+    // status messages, e.g. about save states
+    fn show_message(&mut self, message: &str) {
+        println!("{}", message);
+    }
+
+    // the machine state, shown whenever the debugger stops execution
+    fn show_debugger(&mut self, state: &str) {
+        print!("{}", state);
+    }
+}
+
+pub trait AudioSink {
+    fn set_tone(&mut self, on: bool);
+
+    // an XO-CHIP audio pattern and the rate its bits are played at, in bits per second
+    fn set_pattern(&mut self, _pattern: [u8; AUDIO_PATTERN_SIZE], _playback_rate: f32) {}
+}
+
+pub trait InputSource {
+    // the events that happened since the last poll
+    fn poll(&mut self) -> Vec<InputEvent>;
+}
+
+// for frontends without sound
+pub struct NullAudio;
+
+impl AudioSink for NullAudio {
+    fn set_tone(&mut self, _on: bool) {}
+}
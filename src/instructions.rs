@@ -3,6 +3,12 @@ use std::fmt::Formatter;
 
 pub struct InstructionReadError(u16);
 
+impl InstructionReadError {
+    pub fn opcode(&self) -> u16 {
+        self.0
+    }
+}
+
 impl fmt::Debug for InstructionReadError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "couldn't read instruction {:#06x}", self.0)
@@ -15,17 +21,27 @@ impl fmt::Display for InstructionReadError {
     }
 }
 
+impl std::error::Error for InstructionReadError {}
+
 #[allow(non_camel_case_types)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Instruction {
     SYS    (u16),
+    SCD    (u8),
     CLS    ,
     RET    ,
+    SCR    ,
+    SCL    ,
+    EXIT   ,
+    LOW    ,
+    HIGH   ,
     JP     (u16),
     CALL   (u16),
     SE_RV  (u8, u8),
     SNE_RV (u8, u8),
     SE_RR  (u8, u8),
+    LD_IRXY(u8, u8),
+    LD_RXYI(u8, u8),
     LD_RV  (u8, u8),
     ADD_RV (u8, u8),
     LD_RR  (u8, u8),
@@ -39,6 +55,7 @@ pub enum Instruction {
     SHL    (u8, u8),
     SNE_RR (u8, u8),
     LD_IV  (u16),
+    LD_IL  (u16),
     JP_RV  (u16),
     RND    (u8, u8),
     DRW    (u8, u8, u8),
@@ -50,24 +67,205 @@ pub enum Instruction {
     LD_SR  (u8),
     ADD_IR (u8),
     LD_RF  (u8),
+    LD_RHF (u8),
     LD_BR  (u8),
     LD_IRR (u8),
-    LD_RRI (u8)
+    LD_RRI (u8),
+    LD_PRR (u8),
+    LD_RRP (u8),
+    PLANE  (u8),
+    AUDIO  ,
+    PITCH  (u8)
+}
+
+impl fmt::Display for Instruction {
+    // canonical mnemonics, e.g. `LD V3, 0x0A` or `DRW V0, V1, 5`
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.write_mnemonic(f, None)
+    }
+}
+
+impl From<Instruction> for u16 {
+    // the first word of the instruction, operands are masked to the bits the opcode has room for
+    fn from(inst: Instruction) -> u16 {
+        let xy = |x: u8, y: u8| (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4;
+        let xkk = |x: u8, kk: u8| (x as u16 & 0xF) << 8 | kk as u16;
+        let x = |x: u8| (x as u16 & 0xF) << 8;
+        match inst {
+            Instruction::SYS(n) => n & 0xFFF,
+            Instruction::SCD(n) => 0x00C0 | (n as u16 & 0xF),
+            Instruction::CLS => 0x00E0,
+            Instruction::RET => 0x00EE,
+            Instruction::SCR => 0x00FB,
+            Instruction::SCL => 0x00FC,
+            Instruction::EXIT => 0x00FD,
+            Instruction::LOW => 0x00FE,
+            Instruction::HIGH => 0x00FF,
+            Instruction::JP(n) => 0x1000 | (n & 0xFFF),
+            Instruction::CALL(n) => 0x2000 | (n & 0xFFF),
+            Instruction::SE_RV(r, v) => 0x3000 | xkk(r, v),
+            Instruction::SNE_RV(r, v) => 0x4000 | xkk(r, v),
+            Instruction::SE_RR(r1, r2) => 0x5000 | xy(r1, r2),
+            Instruction::LD_IRXY(r1, r2) => 0x5002 | xy(r1, r2),
+            Instruction::LD_RXYI(r1, r2) => 0x5003 | xy(r1, r2),
+            Instruction::LD_RV(r, v) => 0x6000 | xkk(r, v),
+            Instruction::ADD_RV(r, v) => 0x7000 | xkk(r, v),
+            Instruction::LD_RR(r1, r2) => 0x8000 | xy(r1, r2),
+            Instruction::OR(r1, r2) => 0x8001 | xy(r1, r2),
+            Instruction::AND(r1, r2) => 0x8002 | xy(r1, r2),
+            Instruction::XOR(r1, r2) => 0x8003 | xy(r1, r2),
+            Instruction::ADD_RR(r1, r2) => 0x8004 | xy(r1, r2),
+            Instruction::SUB(r1, r2) => 0x8005 | xy(r1, r2),
+            Instruction::SHR(r1, r2) => 0x8006 | xy(r1, r2),
+            Instruction::SUBN(r1, r2) => 0x8007 | xy(r1, r2),
+            Instruction::SHL(r1, r2) => 0x800E | xy(r1, r2),
+            Instruction::SNE_RR(r1, r2) => 0x9000 | xy(r1, r2),
+            Instruction::LD_IV(n) => 0xA000 | (n & 0xFFF),
+            Instruction::LD_IL(_) => 0xF000,
+            Instruction::JP_RV(n) => 0xB000 | (n & 0xFFF),
+            Instruction::RND(r, v) => 0xC000 | xkk(r, v),
+            Instruction::DRW(r1, r2, n) => 0xD000 | xy(r1, r2) | (n as u16 & 0xF),
+            Instruction::SKP(r) => 0xE09E | x(r),
+            Instruction::SKNP(r) => 0xE0A1 | x(r),
+            Instruction::LD_RD(r) => 0xF007 | x(r),
+            Instruction::LD_RK(r) => 0xF00A | x(r),
+            Instruction::LD_DR(r) => 0xF015 | x(r),
+            Instruction::LD_SR(r) => 0xF018 | x(r),
+            Instruction::ADD_IR(r) => 0xF01E | x(r),
+            Instruction::LD_RF(r) => 0xF029 | x(r),
+            Instruction::LD_RHF(r) => 0xF030 | x(r),
+            Instruction::LD_BR(r) => 0xF033 | x(r),
+            Instruction::LD_IRR(r) => 0xF055 | x(r),
+            Instruction::LD_RRI(r) => 0xF065 | x(r),
+            Instruction::LD_PRR(r) => 0xF075 | x(r),
+            Instruction::LD_RRP(r) => 0xF085 | x(r),
+            Instruction::PLANE(n) => 0xF001 | x(n),
+            Instruction::AUDIO => 0xF002,
+            Instruction::PITCH(r) => 0xF03A | x(r)
+        }
+    }
 }
 
 impl Instruction {
+    // the memory address this instruction refers to, if any
+    pub fn address_operand(&self) -> Option<u16> {
+        match *self {
+            Instruction::SYS(n) | Instruction::JP(n) | Instruction::CALL(n) |
+            Instruction::LD_IV(n) | Instruction::LD_IL(n) | Instruction::JP_RV(n) => Some(n),
+            _ => None
+        }
+    }
+
+    // formats the mnemonic with `address` (usually a label) in place of the address operand
+    pub fn format_with_address(&self, address: &str) -> String {
+        let mut out = String::new();
+        self.write_mnemonic(&mut out, Some(address)).unwrap();
+        out
+    }
+
+    fn write_mnemonic(&self, f: &mut dyn fmt::Write, address: Option<&str>) -> fmt::Result {
+        let addr = |n: u16| address.map(str::to_string).unwrap_or_else(|| format!("{:#05X}", n));
+        match *self {
+            Instruction::SYS(n) => write!(f, "SYS {}", addr(n)),
+            Instruction::SCD(n) => write!(f, "SCD {}", n),
+            Instruction::CLS => write!(f, "CLS"),
+            Instruction::RET => write!(f, "RET"),
+            Instruction::SCR => write!(f, "SCR"),
+            Instruction::SCL => write!(f, "SCL"),
+            Instruction::EXIT => write!(f, "EXIT"),
+            Instruction::LOW => write!(f, "LOW"),
+            Instruction::HIGH => write!(f, "HIGH"),
+            Instruction::JP(n) => write!(f, "JP {}", addr(n)),
+            Instruction::CALL(n) => write!(f, "CALL {}", addr(n)),
+            Instruction::SE_RV(r, v) => write!(f, "SE V{:X}, {:#04X}", r, v),
+            Instruction::SNE_RV(r, v) => write!(f, "SNE V{:X}, {:#04X}", r, v),
+            Instruction::SE_RR(r1, r2) => write!(f, "SE V{:X}, V{:X}", r1, r2),
+            Instruction::LD_IRXY(r1, r2) => write!(f, "SAVE V{:X}, V{:X}", r1, r2),
+            Instruction::LD_RXYI(r1, r2) => write!(f, "LOAD V{:X}, V{:X}", r1, r2),
+            Instruction::LD_RV(r, v) => write!(f, "LD V{:X}, {:#04X}", r, v),
+            Instruction::ADD_RV(r, v) => write!(f, "ADD V{:X}, {:#04X}", r, v),
+            Instruction::LD_RR(r1, r2) => write!(f, "LD V{:X}, V{:X}", r1, r2),
+            Instruction::OR(r1, r2) => write!(f, "OR V{:X}, V{:X}", r1, r2),
+            Instruction::AND(r1, r2) => write!(f, "AND V{:X}, V{:X}", r1, r2),
+            Instruction::XOR(r1, r2) => write!(f, "XOR V{:X}, V{:X}", r1, r2),
+            Instruction::ADD_RR(r1, r2) => write!(f, "ADD V{:X}, V{:X}", r1, r2),
+            Instruction::SUB(r1, r2) => write!(f, "SUB V{:X}, V{:X}", r1, r2),
+            Instruction::SHR(r1, r2) => write!(f, "SHR V{:X}, V{:X}", r1, r2),
+            Instruction::SUBN(r1, r2) => write!(f, "SUBN V{:X}, V{:X}", r1, r2),
+            Instruction::SHL(r1, r2) => write!(f, "SHL V{:X}, V{:X}", r1, r2),
+            Instruction::SNE_RR(r1, r2) => write!(f, "SNE V{:X}, V{:X}", r1, r2),
+            Instruction::LD_IV(n) => write!(f, "LD I, {}", addr(n)),
+            Instruction::LD_IL(n) => write!(f, "LD I, LONG {}", address.map(str::to_string).unwrap_or_else(|| format!("{:#06X}", n))),
+            Instruction::JP_RV(n) => write!(f, "JP V0, {}", addr(n)),
+            Instruction::RND(r, v) => write!(f, "RND V{:X}, {:#04X}", r, v),
+            Instruction::DRW(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SKP(r) => write!(f, "SKP V{:X}", r),
+            Instruction::SKNP(r) => write!(f, "SKNP V{:X}", r),
+            Instruction::LD_RD(r) => write!(f, "LD V{:X}, DT", r),
+            Instruction::LD_RK(r) => write!(f, "LD V{:X}, K", r),
+            Instruction::LD_DR(r) => write!(f, "LD DT, V{:X}", r),
+            Instruction::LD_SR(r) => write!(f, "LD ST, V{:X}", r),
+            Instruction::ADD_IR(r) => write!(f, "ADD I, V{:X}", r),
+            Instruction::LD_RF(r) => write!(f, "LD F, V{:X}", r),
+            Instruction::LD_RHF(r) => write!(f, "LD HF, V{:X}", r),
+            Instruction::LD_BR(r) => write!(f, "LD B, V{:X}", r),
+            Instruction::LD_IRR(r) => write!(f, "LD [I], V{:X}", r),
+            Instruction::LD_RRI(r) => write!(f, "LD V{:X}, [I]", r),
+            Instruction::LD_PRR(r) => write!(f, "LD R, V{:X}", r),
+            Instruction::LD_RRP(r) => write!(f, "LD V{:X}, R", r),
+            Instruction::PLANE(n) => write!(f, "PLANE {}", n),
+            Instruction::AUDIO => write!(f, "AUDIO"),
+            Instruction::PITCH(r) => write!(f, "PITCH V{:X}", r)
+        }
+    }
+
+    // like `read`, but also decodes the four-byte XO-CHIP instructions whose second word is `operand`
+    pub fn read_long(x: u16, operand: u16) -> Result<Instruction, InstructionReadError> {
+        match x {
+            0xF000 => Ok(Instruction::LD_IL(operand)),
+            _ => Instruction::read(x)
+        }
+    }
+
+    // size of the instruction in memory, in bytes
+    pub fn length(&self) -> u16 {
+        match self {
+            Instruction::LD_IL(_) => 4,
+            _ => 2
+        }
+    }
+
+    // appends the `length()` bytes of the instruction as they're stored in memory
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&u16::from(*self).to_be_bytes());
+        if let Instruction::LD_IL(n) = *self {
+            out.extend_from_slice(&n.to_be_bytes());
+        }
+    }
+
     pub fn read(x: u16) -> Result<Instruction, InstructionReadError> {
         match x >> 12 {
             0x0 => match x {
+                0xC0..=0xCF => Ok(Instruction::SCD((x & 0xF) as u8)),
                 0xE0 => Ok(Instruction::CLS),
                 0xEE => Ok(Instruction::RET),
+                0xFB => Ok(Instruction::SCR),
+                0xFC => Ok(Instruction::SCL),
+                0xFD => Ok(Instruction::EXIT),
+                0xFE => Ok(Instruction::LOW),
+                0xFF => Ok(Instruction::HIGH),
                 _ => Ok(Instruction::SYS(x & 0xFFF))
             },
             0x1 => Ok(Instruction::JP(x & 0xFFF)),
             0x2 => Ok(Instruction::CALL(x & 0xFFF)),
             0x3 => Ok(Instruction::SE_RV(((x & 0xF00) >> 8) as u8, (x & 0xFF) as u8)),
             0x4 => Ok(Instruction::SNE_RV(((x & 0xF00) >> 8) as u8, (x & 0xFF) as u8)),
-            0x5 => Ok(Instruction::SE_RR(((x & 0xF00) >> 8) as u8, ((x & 0xF0) >> 4) as u8)),
+            0x5 => match x & 0xF {
+                0x0 => Ok(Instruction::SE_RR(((x & 0xF00) >> 8) as u8, ((x & 0xF0) >> 4) as u8)),
+                0x2 => Ok(Instruction::LD_IRXY(((x & 0xF00) >> 8) as u8, ((x & 0xF0) >> 4) as u8)),
+                0x3 => Ok(Instruction::LD_RXYI(((x & 0xF00) >> 8) as u8, ((x & 0xF0) >> 4) as u8)),
+                _ => Err(InstructionReadError(x))
+            },
             0x6 => Ok(Instruction::LD_RV(((x & 0xF00) >> 8) as u8, (x & 0xFF) as u8)),
             0x7 => Ok(Instruction::ADD_RV(((x & 0xF00) >> 8) as u8, (x & 0xFF) as u8)),
             0x8 => match x & 0xF {
@@ -82,7 +280,10 @@ impl Instruction {
                 0xE => Ok(Instruction::SHL(((x & 0xF00) >> 8) as u8, ((x & 0xF0) >> 4) as u8)),
                 _ => Err(InstructionReadError(x))
             },
-            0x9 => Ok(Instruction::SNE_RR(((x & 0xF00) >> 8) as u8, ((x & 0xF0) >> 4) as u8)),
+            0x9 => match x & 0xF {
+                0x0 => Ok(Instruction::SNE_RR(((x & 0xF00) >> 8) as u8, ((x & 0xF0) >> 4) as u8)),
+                _ => Err(InstructionReadError(x))
+            },
             0xA => Ok(Instruction::LD_IV(x & 0xFFF)),
             0xB => Ok(Instruction::JP_RV(x & 0xFFF)),
             0xC => Ok(Instruction::RND(((x & 0xF00) >> 8) as u8, (x & 0xFF) as u8)),
@@ -93,15 +294,21 @@ impl Instruction {
                 _ => Err(InstructionReadError(x))
             },
             0xF => match x & 0xFF {
+                0x01 => Ok(Instruction::PLANE(((x & 0xF00) >> 8) as u8)),
+                0x02 if x == 0xF002 => Ok(Instruction::AUDIO),
                 0x07 => Ok(Instruction::LD_RD(((x & 0xF00) >> 8) as u8)),
                 0x0A => Ok(Instruction::LD_RK(((x & 0xF00) >> 8) as u8)),
                 0x15 => Ok(Instruction::LD_DR(((x & 0xF00) >> 8) as u8)),
                 0x18 => Ok(Instruction::LD_SR(((x & 0xF00) >> 8) as u8)),
                 0x1E => Ok(Instruction::ADD_IR(((x & 0xF00) >> 8) as u8)),
                 0x29 => Ok(Instruction::LD_RF(((x & 0xF00) >> 8) as u8)),
+                0x30 => Ok(Instruction::LD_RHF(((x & 0xF00) >> 8) as u8)),
                 0x33 => Ok(Instruction::LD_BR(((x & 0xF00) >> 8) as u8)),
+                0x3A => Ok(Instruction::PITCH(((x & 0xF00) >> 8) as u8)),
                 0x55 => Ok(Instruction::LD_IRR(((x & 0xF00) >> 8) as u8)),
                 0x65 => Ok(Instruction::LD_RRI(((x & 0xF00) >> 8) as u8)),
+                0x75 => Ok(Instruction::LD_PRR(((x & 0xF00) >> 8) as u8)),
+                0x85 => Ok(Instruction::LD_RRP(((x & 0xF00) >> 8) as u8)),
                 _ => Err(InstructionReadError(x))
             },
             _ => Err(InstructionReadError(x))
@@ -1,3 +1,5 @@
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
 pub enum KeyEvent {
     Pressed(u8),
     Released(u8)
@@ -6,7 +8,11 @@ pub enum KeyEvent {
 #[derive(Default)]
 pub struct Keyboard {
     key_states: [bool; 16],
-    pub last_pressed: Option<u8>
+    // masks of the keys that went down/up during the last update, a key can do both
+    pressed_edges: u16,
+    released_edges: u16,
+    // number of updates so far, lets a waiting FX0A ignore edges from before it started
+    updates: u32
 }
 
 impl Keyboard {
@@ -14,19 +20,67 @@ impl Keyboard {
         self.key_states[key]
     }
 
+    pub fn was_pressed(&self, key: usize) -> bool {
+        self.pressed_edges & (1 << key) != 0
+    }
+
+    pub fn was_released(&self, key: usize) -> bool {
+        self.released_edges & (1 << key) != 0
+    }
+
+    // the lowest key that went down during the last update
+    pub fn first_pressed(&self) -> Option<u8> {
+        (self.pressed_edges != 0).then(|| self.pressed_edges.trailing_zeros() as u8)
+    }
+
+    pub fn updates(&self) -> u32 {
+        self.updates
+    }
+
+    // applies the events of one frame, the edges of the previous frame are forgotten
     pub fn update_key_states(&mut self, events: Vec<KeyEvent>) {
-        self.last_pressed = None;
+        self.pressed_edges = 0;
+        self.released_edges = 0;
+        self.updates = self.updates.wrapping_add(1);
 
         for event in events {
             match event {
                 KeyEvent::Pressed(k) => {
+                    if !self.key_states[k as usize] {
+                        self.pressed_edges |= 1 << k;
+                    }
                     self.key_states[k as usize] = true;
-                    self.last_pressed = Some(k);
                 }
                 KeyEvent::Released(k) => {
+                    if self.key_states[k as usize] {
+                        self.released_edges |= 1 << k;
+                    }
                     self.key_states[k as usize] = false;
                 }
             }
         }
     }
-}
\ No newline at end of file
+
+    pub(crate) fn write_state(&self, out: &mut StateWriter) {
+        let key_mask = self.key_states.iter().enumerate()
+            .fold(0u16, |mask, (key, &pressed)| mask | (pressed as u16) << key);
+        out.write_u16(key_mask);
+        out.write_u16(self.pressed_edges);
+        out.write_u16(self.released_edges);
+        out.write_u32(self.updates);
+    }
+
+    pub(crate) fn read_state(input: &mut StateReader) -> Result<Keyboard, SaveStateError> {
+        let key_mask = input.read_u16()?;
+        let mut key_states = [false; 16];
+        for (key, pressed) in key_states.iter_mut().enumerate() {
+            *pressed = key_mask & (1 << key) != 0;
+        }
+        Ok(Keyboard {
+            key_states,
+            pressed_edges: input.read_u16()?,
+            released_edges: input.read_u16()?,
+            updates: input.read_u32()?
+        })
+    }
+}
@@ -3,40 +3,79 @@ pub mod cpu;
 pub mod instructions;
 pub mod keyboard;
 pub mod display;
+pub mod quirks;
+pub mod config;
+pub mod debugger;
+pub mod disassembler;
+pub mod assembler;
+pub mod savestate;
+pub mod random;
+pub mod frontend;
+pub mod movie;
+pub mod rewind;
+pub mod runner;
+pub mod timing;
+pub mod romdb;
+pub mod blocks;
 
+use crate::blocks::{Backend, BlockCache};
 use crate::memory::RAM;
-use crate::cpu::CPU;
+use crate::cpu::{CPU, ExecutionError};
 use crate::display::Display;
 use crate::instructions::Instruction;
 use crate::keyboard::Keyboard;
+use crate::quirks::Quirks;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
 
 pub struct Chip8 {
     pub cpu: CPU,
     pub ram: RAM,
     pub keyboard: Keyboard,
-    pub display: Display
+    pub display: Display,
+    pub quirks: Quirks,
+    pub backend: Backend,
+    blocks: BlockCache
 }
 
 impl Default for Chip8 {
     fn default() -> Self {
+        Chip8::new(Quirks::default())
+    }
+}
+
+impl Chip8 {
+    pub fn new(quirks: Quirks) -> Self {
+        Chip8::with_ram_size(quirks, memory::RAM_SIZE)
+    }
+
+    // XO-CHIP programs expect memory::XO_RAM_SIZE bytes of memory
+    pub fn with_ram_size(quirks: Quirks, ram_size: usize) -> Self {
         let cpu = CPU::default();
-        let ram = RAM::default();
+        let ram = RAM::new(ram_size);
         let keyboard = Keyboard::default();
         let display = Display::default();
-        Chip8 { cpu, ram, keyboard, display }
+        Chip8 { cpu, ram, keyboard, display, quirks, backend: Backend::default(), blocks: BlockCache::default() }
     }
-}
 
-impl Chip8 {
-    pub fn execute(&mut self, instruction: Instruction) {
-        self.cpu.execute(&mut self.ram, &self.keyboard, &mut self.display, instruction)
+    pub fn execute(&mut self, instruction: Instruction) -> Result<(), ExecutionError> {
+        self.cpu.execute(&mut self.ram, &self.keyboard, &mut self.display, &self.quirks, instruction)
     }
 
-    pub fn step(&mut self) {
-        let inst = self.ram.read_instruction(self.cpu.program_counter as usize)
-            .unwrap_or_else(|_| panic!("Memory contained invalid instruction at position {:#06x}", self.cpu.program_counter));
-        println!("at {:#06x}: {:?}", self.cpu.program_counter, inst);
-        self.cpu.execute(&mut self.ram, &self.keyboard, &mut self.display, inst);
+    // does nothing once the program has halted itself through EXIT
+    pub fn step(&mut self) -> Result<(), ExecutionError> {
+        if self.cpu.is_halted() || self.cpu.is_waiting_for_vblank() {
+            return Ok(());
+        }
+        let pc = self.cpu.program_counter;
+        if pc as usize + 2 > self.ram.size() {
+            return Err(ExecutionError::MemoryOutOfRange { address: pc as usize, length: 2 });
+        }
+        let inst = self.ram.decode_instruction(pc as usize)
+            .map_err(|e| ExecutionError::InvalidInstruction { address: pc, opcode: e.opcode() })?;
+        if pc as usize + inst.length() as usize > self.ram.size() {
+            return Err(ExecutionError::MemoryOutOfRange { address: pc as usize, length: inst.length() as usize });
+        }
+        self.cpu.execute(&mut self.ram, &self.keyboard, &mut self.display, &self.quirks, inst)
     }
 
     pub fn load_program(&mut self, program_data: &[u8]) {
@@ -44,4 +83,61 @@ impl Chip8 {
         let program_offset = memory.copy_program(program_data);
         self.cpu.program_counter = program_offset as u16;
     }
-}
\ No newline at end of file
+
+    // runs up to `instructions` instructions with the selected backend, stopping early when the
+    // program halts, waits for the display or faults, and returns how many of them completed
+    pub fn run(&mut self, instructions: u32) -> (u32, Result<(), ExecutionError>) {
+        match self.backend {
+            Backend::Interpreter => {
+                let mut executed = 0;
+                while executed < instructions && !self.cpu.is_halted() && !self.cpu.is_waiting_for_vblank() {
+                    if let Err(e) = self.step() {
+                        return (executed, Err(e));
+                    }
+                    executed += 1;
+                }
+                (executed, Ok(()))
+            }
+            Backend::Blocks => blocks::run(self, instructions)
+        }
+    }
+
+    // runs up to `instructions` instructions and ends the frame, like the Runner does when not debugging
+    pub fn run_frame(&mut self, instructions: u32) -> Result<(), ExecutionError> {
+        self.run(instructions).1?;
+        if !self.cpu.is_halted() {
+            self.cpu.countdown_timers();
+        }
+        Ok(())
+    }
+
+    // snapshots the whole machine, including its quirks and the state of the random number generator
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = StateWriter::new();
+        self.cpu.write_state(&mut out);
+        self.ram.write_state(&mut out);
+        self.keyboard.write_state(&mut out);
+        self.display.write_state(&mut out);
+        self.quirks.write_state(&mut out);
+        out.into_bytes()
+    }
+
+    // the machine is left untouched if the state can't be read
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut input = StateReader::new(data)?;
+        let cpu = CPU::read_state(&mut input)?;
+        let ram = RAM::read_state(&mut input)?;
+        let keyboard = Keyboard::read_state(&mut input)?;
+        let display = Display::read_state(&mut input)?;
+        let quirks = Quirks::read_state(&mut input)?;
+        input.finish()?;
+
+        // the backend stays selected, the new memory drops the translated blocks
+        self.cpu = cpu;
+        self.ram = ram;
+        self.keyboard = keyboard;
+        self.display = display;
+        self.quirks = quirks;
+        Ok(())
+    }
+}
@@ -1,10 +1,14 @@
 use std::cmp::min;
+use std::ops::Range;
 use crate::Instruction;
+use crate::cpu::ExecutionError;
 use crate::instructions::InstructionReadError;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
 
 pub const RAM_INTPT_OFFSET: usize = 0x200;
 pub const RAM_SIZE: usize = 0x1000;
 pub const PROGRAM_MEMORY_SIZE: usize = RAM_SIZE - RAM_INTPT_OFFSET;
+pub const XO_RAM_SIZE: usize = 0x10000;
 
 pub const INTPT_SPRITE_LENGTH: usize = 5;
 const INTPT_SPRITES: [u8; 16*5] = [
@@ -15,7 +19,7 @@ const INTPT_SPRITES: [u8; 16*5] = [
     0x90, 0x90, 0xF0, 0x10, 0x10, // 4
     0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
     0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 7
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
     0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
     0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
     0xF0, 0x90, 0xF0, 0x90, 0x90, // A
@@ -26,20 +30,61 @@ const INTPT_SPRITES: [u8; 16*5] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+pub const INTPT_HIRES_SPRITE_OFFSET: usize = INTPT_SPRITES.len();
+pub const INTPT_HIRES_SPRITE_LENGTH: usize = 10;
+const INTPT_HIRES_SPRITES: [u8; 16*10] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
 pub struct RAM {
-    mem: [u8; RAM_SIZE]
+    mem: Box<[u8]>,
+    // what `decode_instruction` read at each address, forgotten when any of its bytes are written,
+    // only as long as the highest address decoded so far
+    decoded: Vec<Option<Instruction>>,
+    // the bytes written since the block backend last looked, all of them for new memory
+    written: Option<Range<usize>>
 }
 
 impl Default for RAM {
     fn default() -> Self {
-        let mut mem = [0; RAM_SIZE];
-        mem[..INTPT_SPRITES.len()].copy_from_slice(&INTPT_SPRITES);
-        RAM { mem }
+        RAM::new(RAM_SIZE)
     }
 }
 
 impl RAM {
+    // `size` is RAM_SIZE for CHIP-8/SUPER-CHIP and XO_RAM_SIZE for XO-CHIP
+    pub fn new(size: usize) -> Self {
+        let mut mem = vec![0; size].into_boxed_slice();
+        mem[..INTPT_SPRITES.len()].copy_from_slice(&INTPT_SPRITES);
+        mem[INTPT_HIRES_SPRITE_OFFSET..INTPT_HIRES_SPRITE_OFFSET+INTPT_HIRES_SPRITES.len()].copy_from_slice(&INTPT_HIRES_SPRITES);
+        RAM { written: Some(0..mem.len()), mem, decoded: Vec::new() }
+    }
+
+    pub fn size(&self) -> usize {
+        self.mem.len()
+    }
+
+    pub fn program_memory_size(&self) -> usize {
+        self.mem.len() - RAM_INTPT_OFFSET
+    }
+
     pub fn borrow_memory_range_mut(&mut self, address: usize, range: usize) -> &mut [u8] {
+        self.invalidate(address, range);
         &mut self.mem[address..address+range]
     }
 
@@ -48,6 +93,8 @@ impl RAM {
     }
 
     pub fn borrow_memory_mut(&mut self) -> &mut [u8] {
+        self.decoded.clear();
+        self.written = Some(0..self.mem.len());
         &mut self.mem
     }
 
@@ -56,13 +103,89 @@ impl RAM {
     }
 
     pub fn copy_program(&mut self, program_data: &[u8]) -> usize {
-        let len = min(PROGRAM_MEMORY_SIZE, program_data.len());
+        let len = min(self.program_memory_size(), program_data.len());
+        self.invalidate(RAM_INTPT_OFFSET, len);
         self.mem[RAM_INTPT_OFFSET..RAM_INTPT_OFFSET+len].copy_from_slice(&program_data[..len]);
         RAM_INTPT_OFFSET
     }
 
+    pub(crate) fn write_state(&self, out: &mut StateWriter) {
+        out.write_u32(self.mem.len() as u32);
+        out.write_bytes(&self.mem);
+    }
+
+    pub(crate) fn read_state(input: &mut StateReader) -> Result<RAM, SaveStateError> {
+        let size = input.read_u32()? as usize;
+        if !(RAM_INTPT_OFFSET..=XO_RAM_SIZE).contains(&size) {
+            return Err(SaveStateError::Invalid("memory size"));
+        }
+        let mem: Box<[u8]> = input.read_bytes(size)?.into();
+        Ok(RAM { written: Some(0..mem.len()), mem, decoded: Vec::new() })
+    }
+
+    // the word following `pc` is only used by the four-byte XO-CHIP instructions, it reads
+    // as zero past the end of memory
     pub fn read_instruction(&self, pc: usize) -> Result<Instruction, InstructionReadError> {
-        let inst_data = (self.mem[pc] as u16) << 8 | self.mem[pc + 1] as u16;
-        Instruction::read(inst_data)
+        let inst_data = self.read_word(pc).unwrap_or(0);
+        let operand = self.read_word(pc + 2).unwrap_or(0);
+        Instruction::read_long(inst_data, operand)
+    }
+
+    // nothing is written when the instruction doesn't fit in memory at `address`
+    pub fn write_instruction(&mut self, address: usize, inst: Instruction) -> Result<(), ExecutionError> {
+        let length = inst.length() as usize;
+        let target = self.mem.get_mut(address..).and_then(|mem| mem.get_mut(..length))
+            .ok_or(ExecutionError::MemoryOutOfRange { address, length })?;
+        let mut bytes = Vec::with_capacity(length);
+        inst.write(&mut bytes);
+        target.copy_from_slice(&bytes);
+        self.invalidate(address, length);
+        Ok(())
+    }
+
+    // like `read_instruction`, but only decodes the bytes at `pc` again after they've been written
+    #[inline]
+    pub fn decode_instruction(&mut self, pc: usize) -> Result<Instruction, InstructionReadError> {
+        match self.decoded.get(pc) {
+            Some(Some(inst)) => Ok(*inst),
+            _ => self.decode_uncached(pc)
+        }
+    }
+
+    #[cold]
+    fn decode_uncached(&mut self, pc: usize) -> Result<Instruction, InstructionReadError> {
+        let inst = self.read_instruction(pc)?;
+        if pc < self.mem.len() {
+            if self.decoded.len() <= pc {
+                self.decoded.resize(pc + 1, None);
+            }
+            self.decoded[pc] = Some(inst);
+        }
+        Ok(inst)
+    }
+
+    // forgets the instructions overlapping `range` bytes from `address`, which includes the long
+    // loads starting up to three bytes earlier
+    fn invalidate(&mut self, address: usize, range: usize) {
+        let end = address.saturating_add(range).min(self.mem.len());
+        let start = address.min(end);
+        self.written = Some(match self.written.take() {
+            Some(written) => written.start.min(start)..written.end.max(end),
+            None => start..end
+        });
+
+        let end = end.min(self.decoded.len());
+        let start = address.saturating_sub(3).min(end);
+        self.decoded[start..end].fill(None);
+    }
+
+    // the range of bytes written since the last call, if any
+    pub(crate) fn take_written(&mut self) -> Option<Range<usize>> {
+        self.written.take()
+    }
+
+    fn read_word(&self, address: usize) -> Option<u16> {
+        let bytes = self.mem.get(address..address+2)?;
+        Some((bytes[0] as u16) << 8 | bytes[1] as u16)
     }
 }
\ No newline at end of file
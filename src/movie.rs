@@ -0,0 +1,232 @@
+use std::fmt;
+use std::fmt::Formatter;
+use sha1::{Digest, Sha1};
+use crate::{Chip8, memory};
+use crate::keyboard::{KeyEvent, Keyboard};
+use crate::quirks::Quirks;
+use crate::random::RandomSource;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+// bumped whenever the layout below changes, older movies are rejected
+pub const MOVIE_VERSION: u16 = 1;
+const MOVIE_MAGIC: &[u8; 4] = b"C8MV";
+
+pub type Hash = [u8; 20];
+
+// the SHA-1 of a ROM, which a movie only replays on
+pub fn rom_hash(rom: &[u8]) -> Hash {
+    Sha1::digest(rom).into()
+}
+
+// lowercase hexadecimal, the way `sha1sum` prints it
+pub fn hash_to_hex(hash: &Hash) -> String {
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// a hash of what a player can observe: the display and the registers
+pub fn checksum(system: &Chip8) -> Hash {
+    let mut hasher = Sha1::new();
+    let display = &system.display;
+    hasher.update([display.is_hires() as u8]);
+    for column in display.borrow_display() {
+        hasher.update(column);
+    }
+    let cpu = &system.cpu;
+    hasher.update(cpu.v_registers());
+    hasher.update(cpu.i_register().to_be_bytes());
+    hasher.update(cpu.program_counter.to_be_bytes());
+    for address in cpu.stack() {
+        hasher.update(address.to_be_bytes());
+    }
+    hasher.update([cpu.delay_timer(), cpu.sound_timer()]);
+    hasher.finalize().into()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RngKind {
+    ChaCha,
+    Vip
+}
+
+// the keypad right after a frame's update: the held keys and the keys that went down/up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameInput {
+    pub keys: u16,
+    pub pressed: u16,
+    pub released: u16
+}
+
+impl FrameInput {
+    pub fn capture(keyboard: &Keyboard) -> Self {
+        let mask = |test: &dyn Fn(usize) -> bool| (0..16).fold(0u16, |mask, key| mask | (test(key) as u16) << key);
+        FrameInput {
+            keys: mask(&|key| keyboard.is_pressed(key)),
+            pressed: mask(&|key| keyboard.was_pressed(key)),
+            released: mask(&|key| keyboard.was_released(key))
+        }
+    }
+
+    // the events that take a keyboard holding the `previous` keys to this frame's keys and edges
+    pub fn events(&self, previous: u16) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        for key in 0..16u8 {
+            let bit = 1 << key;
+            match (self.pressed & bit != 0, self.released & bit != 0) {
+                // went both ways within the frame, the order depends on where it started
+                (true, true) if previous & bit != 0 => events.extend([KeyEvent::Released(key), KeyEvent::Pressed(key)]),
+                (true, true) => events.extend([KeyEvent::Pressed(key), KeyEvent::Released(key)]),
+                (true, false) => events.push(KeyEvent::Pressed(key)),
+                (false, true) => events.push(KeyEvent::Released(key)),
+                (false, false) => {}
+            }
+        }
+        events
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Invalid(SaveStateError),
+    WrongRom,
+    ChecksumMismatch { frame: usize }
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::BadMagic => write!(f, "not a chip8 movie"),
+            MovieError::UnsupportedVersion(version)
+                => write!(f, "unsupported movie version {} (expected {})", version, MOVIE_VERSION),
+            MovieError::Invalid(e) => write!(f, "invalid movie: {}", e),
+            MovieError::WrongRom => write!(f, "the movie was recorded with another ROM"),
+            MovieError::ChecksumMismatch { frame }
+                => write!(f, "the replay diverged from the recording, its state differs after frame {}", frame)
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<SaveStateError> for MovieError {
+    fn from(e: SaveStateError) -> Self {
+        match e {
+            SaveStateError::BadMagic => MovieError::BadMagic,
+            SaveStateError::UnsupportedVersion(version) => MovieError::UnsupportedVersion(version),
+            e => MovieError::Invalid(e)
+        }
+    }
+}
+
+// everything needed to play a session back exactly: how the machine was set up and the keypad of every frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: Hash,
+    pub ram_size: usize,
+    pub quirks: Quirks,
+    pub rng: RngKind,
+    pub seed: u64,
+    pub instructions_per_frame: u32,
+    pub frames: Vec<FrameInput>,
+    // checksum() of the machine once the last frame ran
+    pub checksum: Hash
+}
+
+impl Movie {
+    pub fn new(rom: &[u8], ram_size: usize, quirks: Quirks, rng: RngKind, seed: u64, instructions_per_frame: u32) -> Self {
+        Movie {
+            rom_hash: rom_hash(rom),
+            ram_size,
+            quirks,
+            rng,
+            seed,
+            instructions_per_frame,
+            frames: Vec::new(),
+            checksum: [0; 20]
+        }
+    }
+
+    // a machine set up like the one the movie was recorded on, with the ROM loaded
+    pub fn create_system(&self, rom: &[u8]) -> Result<Chip8, MovieError> {
+        if rom_hash(rom) != self.rom_hash {
+            return Err(MovieError::WrongRom);
+        }
+        let mut system = Chip8::with_ram_size(self.quirks, self.ram_size);
+        system.load_program(rom);
+        system.cpu.set_random_source(match self.rng {
+            RngKind::ChaCha => RandomSource::seeded(self.seed),
+            RngKind::Vip => RandomSource::vip(self.seed)
+        });
+        Ok(system)
+    }
+
+    // replays every frame on a fresh machine, which must end up with the recorded checksum
+    pub fn verify(&self, rom: &[u8]) -> Result<Chip8, MovieError> {
+        let mut system = self.create_system(rom)?;
+        let mut keys = 0;
+        for frame in &self.frames {
+            system.keyboard.update_key_states(frame.events(keys));
+            keys = frame.keys;
+            // a recording ends where its program faulted or exited
+            if system.run_frame(self.instructions_per_frame).is_err() || system.cpu.is_halted() {
+                break;
+            }
+        }
+        if checksum(&system) != self.checksum {
+            return Err(MovieError::ChecksumMismatch { frame: self.frames.len() });
+        }
+        Ok(system)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = StateWriter::with_header(MOVIE_MAGIC, MOVIE_VERSION);
+        out.write_bytes(&self.rom_hash);
+        out.write_u32(self.ram_size as u32);
+        self.quirks.write_state(&mut out);
+        out.write_u8(match self.rng {
+            RngKind::ChaCha => 0,
+            RngKind::Vip => 1
+        });
+        out.write_u64(self.seed);
+        out.write_u32(self.instructions_per_frame);
+        out.write_u32(self.frames.len() as u32);
+        for frame in &self.frames {
+            out.write_u16(frame.keys);
+            out.write_u16(frame.pressed);
+            out.write_u16(frame.released);
+        }
+        out.write_bytes(&self.checksum);
+        out.into_bytes()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, MovieError> {
+        let mut input = StateReader::with_header(data, MOVIE_MAGIC, MOVIE_VERSION)?;
+        let rom_hash = input.read_array()?;
+        let ram_size = input.read_u32()? as usize;
+        if !(memory::RAM_INTPT_OFFSET..=memory::XO_RAM_SIZE).contains(&ram_size) {
+            return Err(MovieError::Invalid(SaveStateError::Invalid("memory size")));
+        }
+        let quirks = Quirks::read_state(&mut input)?;
+        let rng = match input.read_u8()? {
+            0 => RngKind::ChaCha,
+            1 => RngKind::Vip,
+            _ => return Err(MovieError::Invalid(SaveStateError::Invalid("random source")))
+        };
+        let seed = input.read_u64()?;
+        let instructions_per_frame = input.read_u32()?;
+        let frame_count = input.read_u32()?;
+        let mut frames = Vec::new();
+        for _ in 0..frame_count {
+            frames.push(FrameInput {
+                keys: input.read_u16()?,
+                pressed: input.read_u16()?,
+                released: input.read_u16()?
+            });
+        }
+        let checksum = input.read_array()?;
+        input.finish()?;
+
+        Ok(Movie { rom_hash, ram_size, quirks, rng, seed, instructions_per_frame, frames, checksum })
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexIncrement {
+    Unchanged,
+    ByX,
+    ByXPlusOne
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    // SHR/SHL copy Vy into Vx before shifting
+    pub shift_uses_vy: bool,
+    // how LD [I], Vx / LD Vx, [I] leave the I register
+    pub index_increment: IndexIncrement,
+    // JP V0, addr is decoded as BXNN and jumps to XNN + Vx
+    pub jump_uses_vx: bool,
+    // OR/AND/XOR reset VF to 0
+    pub logic_resets_vf: bool,
+    // sprites drawn past an edge wrap to the other side instead of being clipped
+    pub wrap_sprites: bool,
+    // DRW stalls the CPU until the next 60Hz timer tick, limiting draws to one per frame
+    pub display_wait: bool,
+    // FX0A completes once the pressed key is released, like on the VIP, rather than as soon as it's pressed
+    pub key_wait_release: bool
+}
+
+impl Quirks {
+    pub const VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        index_increment: IndexIncrement::ByXPlusOne,
+        jump_uses_vx: false,
+        logic_resets_vf: true,
+        wrap_sprites: false,
+        display_wait: true,
+        key_wait_release: true
+    };
+
+    pub const CHIP48: Quirks = Quirks {
+        shift_uses_vy: false,
+        index_increment: IndexIncrement::ByX,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        wrap_sprites: false,
+        display_wait: false,
+        key_wait_release: true
+    };
+
+    pub const SCHIP: Quirks = Quirks {
+        shift_uses_vy: false,
+        index_increment: IndexIncrement::Unchanged,
+        jump_uses_vx: true,
+        logic_resets_vf: false,
+        wrap_sprites: false,
+        display_wait: false,
+        key_wait_release: true
+    };
+
+    pub const XOCHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        index_increment: IndexIncrement::ByXPlusOne,
+        jump_uses_vx: false,
+        logic_resets_vf: false,
+        wrap_sprites: true,
+        display_wait: false,
+        key_wait_release: true
+    };
+
+    pub const PRESET_NAMES: [&'static str; 4] = ["vip", "chip48", "schip", "xochip"];
+
+    pub(crate) fn write_state(&self, out: &mut StateWriter) {
+        out.write_bool(self.shift_uses_vy);
+        out.write_u8(match self.index_increment {
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::ByX => 1,
+            IndexIncrement::ByXPlusOne => 2
+        });
+        out.write_bool(self.jump_uses_vx);
+        out.write_bool(self.logic_resets_vf);
+        out.write_bool(self.wrap_sprites);
+        out.write_bool(self.display_wait);
+        out.write_bool(self.key_wait_release);
+    }
+
+    pub(crate) fn read_state(input: &mut StateReader) -> Result<Quirks, SaveStateError> {
+        Ok(Quirks {
+            shift_uses_vy: input.read_bool()?,
+            index_increment: match input.read_u8()? {
+                0 => IndexIncrement::Unchanged,
+                1 => IndexIncrement::ByX,
+                2 => IndexIncrement::ByXPlusOne,
+                _ => return Err(SaveStateError::Invalid("index increment quirk"))
+            },
+            jump_uses_vx: input.read_bool()?,
+            logic_resets_vf: input.read_bool()?,
+            wrap_sprites: input.read_bool()?,
+            display_wait: input.read_bool()?,
+            key_wait_release: input.read_bool()?
+        })
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::VIP
+    }
+}
+
+pub struct UnknownQuirksError(String);
+
+impl fmt::Debug for UnknownQuirksError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown quirks preset \"{}\"", self.0)
+    }
+}
+
+impl fmt::Display for UnknownQuirksError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown quirks preset \"{}\" (expected one of {})", self.0, Quirks::PRESET_NAMES.join(", "))
+    }
+}
+
+impl std::error::Error for UnknownQuirksError {}
+
+impl FromStr for Quirks {
+    type Err = UnknownQuirksError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "vip" | "chip8" | "chip-8" => Ok(Quirks::VIP),
+            "chip48" | "chip-48" => Ok(Quirks::CHIP48),
+            "schip" | "schip1.1" | "superchip" => Ok(Quirks::SCHIP),
+            "xochip" | "xo-chip" => Ok(Quirks::XOCHIP),
+            _ => Err(UnknownQuirksError(s.to_string()))
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use crate::memory::RAM;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+// modelled on the COSMAC VIP interpreter's RND routine: a 16-bit counter (R9 on the VIP) is
+// advanced on every call and the byte it points to in the first memory page is added to its
+// high byte, which becomes the result. On the VIP that page held the interpreter itself,
+// here it holds the fonts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VipRandom {
+    counter: u16
+}
+
+impl VipRandom {
+    pub fn new(seed: u16) -> Self {
+        VipRandom { counter: seed }
+    }
+
+    fn next_byte(&mut self, ram: &RAM) -> u8 {
+        let [high, low] = self.counter.wrapping_add(1).to_be_bytes();
+        let value = ram.borrow_memory()[low as usize].wrapping_add(high);
+        self.counter = u16::from_be_bytes([value, low]);
+        value
+    }
+}
+
+// the generator behind RND, its state is part of the machine's save states
+#[derive(Debug, Clone)]
+pub enum RandomSource {
+    ChaCha(Box<ChaCha8Rng>),
+    Vip(VipRandom)
+}
+
+impl Default for RandomSource {
+    fn default() -> Self {
+        RandomSource::ChaCha(Box::new(ChaCha8Rng::from_entropy()))
+    }
+}
+
+impl RandomSource {
+    // the same seed always produces the same sequence of numbers
+    pub fn seeded(seed: u64) -> Self {
+        RandomSource::ChaCha(Box::new(ChaCha8Rng::seed_from_u64(seed)))
+    }
+
+    pub fn vip(seed: u64) -> Self {
+        RandomSource::Vip(VipRandom::new(seed as u16))
+    }
+
+    pub fn next_byte(&mut self, ram: &RAM) -> u8 {
+        match self {
+            RandomSource::ChaCha(rng) => rng.next_u32() as u8,
+            RandomSource::Vip(vip) => vip.next_byte(ram)
+        }
+    }
+
+    pub(crate) fn write_state(&self, out: &mut StateWriter) {
+        match self {
+            RandomSource::ChaCha(rng) => {
+                out.write_u8(0);
+                out.write_bytes(&rng.get_seed());
+                out.write_u64(rng.get_stream());
+                out.write_u128(rng.get_word_pos());
+            }
+            RandomSource::Vip(vip) => {
+                out.write_u8(1);
+                out.write_u16(vip.counter);
+            }
+        }
+    }
+
+    pub(crate) fn read_state(input: &mut StateReader) -> Result<RandomSource, SaveStateError> {
+        match input.read_u8()? {
+            0 => {
+                let mut rng = ChaCha8Rng::from_seed(input.read_array()?);
+                rng.set_stream(input.read_u64()?);
+                rng.set_word_pos(input.read_u128()?);
+                Ok(RandomSource::ChaCha(Box::new(rng)))
+            }
+            1 => Ok(RandomSource::Vip(VipRandom::new(input.read_u16()?))),
+            _ => Err(SaveStateError::Invalid("random generator"))
+        }
+    }
+}
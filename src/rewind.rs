@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use crate::Chip8;
+
+// 10 seconds worth of 60Hz frames
+pub const DEFAULT_REWIND_FRAMES: usize = 600;
+
+// keeps the machine state of the last frames, the newest one in full and each older one as
+// its difference to the next newer one, which is mostly runs of unchanged bytes
+pub struct RewindBuffer {
+    capacity: usize,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize) -> Self {
+        RewindBuffer {
+            capacity,
+            newest: None,
+            deltas: VecDeque::with_capacity(capacity)
+        }
+    }
+
+    // the number of frames that can be stepped back
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+    }
+
+    // to be called at the end of every frame
+    pub fn record(&mut self, system: &Chip8) {
+        let state = system.save_state();
+        if let Some(newest) = self.newest.take() {
+            if self.capacity == 0 {
+                return;
+            }
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(diff(&newest, &state));
+        }
+        self.newest = Some(state);
+    }
+
+    // puts the machine back as it was `frames` recorded frames ago, or as far back as the
+    // buffer goes, returns the number of frames actually stepped back
+    pub fn step_back(&mut self, system: &mut Chip8, frames: usize) -> usize {
+        let mut newest = match self.newest.take() {
+            Some(newest) => newest,
+            None => return 0
+        };
+        let frames = frames.min(self.deltas.len());
+        for _ in 0..frames {
+            newest = undiff(&newest, &self.deltas.pop_back().unwrap());
+        }
+        system.load_state(&newest).expect("recorded states are always valid");
+        self.newest = Some(newest);
+        frames
+    }
+}
+
+fn byte_at(data: &[u8], index: usize) -> u8 {
+    data.get(index).copied().unwrap_or(0)
+}
+
+// encodes `older` against `newer` as its length followed by (unchanged count, changed count,
+// changed bytes) runs, the counts being little endian u16s
+fn diff(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(older.len() as u32).to_le_bytes());
+
+    let mut index = 0;
+    while index < older.len() {
+        let unchanged_start = index;
+        while index < older.len() && index - unchanged_start < u16::MAX as usize && older[index] == byte_at(newer, index) {
+            index += 1;
+        }
+        let changed_start = index;
+        while index < older.len() && index - changed_start < u16::MAX as usize && older[index] != byte_at(newer, index) {
+            index += 1;
+        }
+        out.extend_from_slice(&((changed_start - unchanged_start) as u16).to_le_bytes());
+        out.extend_from_slice(&((index - changed_start) as u16).to_le_bytes());
+        out.extend_from_slice(&older[changed_start..index]);
+    }
+    out
+}
+
+fn undiff(newer: &[u8], delta: &[u8]) -> Vec<u8> {
+    let read_u16 = |at: usize| u16::from_le_bytes([delta[at], delta[at + 1]]) as usize;
+    let length = u32::from_le_bytes([delta[0], delta[1], delta[2], delta[3]]) as usize;
+    let mut older = Vec::with_capacity(length);
+
+    let mut position = 4;
+    while older.len() < length {
+        let (unchanged, changed) = (read_u16(position), read_u16(position + 2));
+        position += 4;
+        for _ in 0..unchanged {
+            older.push(byte_at(newer, older.len()));
+        }
+        older.extend_from_slice(&delta[position..position + changed]);
+        position += changed;
+    }
+    older
+}
@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+use std::fs;
+use serde::{Deserialize, Serialize};
+use crate::config::{self, Names, parse_keypad_key};
+use crate::disassembler::{self, ListingItem};
+use crate::instructions::Instruction;
+use crate::memory::{RAM_INTPT_OFFSET, RAM_SIZE, XO_RAM_SIZE};
+use crate::movie::{Hash, hash_to_hex, rom_hash};
+use crate::quirks::{IndexIncrement, Quirks};
+
+pub const DATABASE_FILE_NAME: &str = "roms.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    #[default]
+    Chip8,
+    Schip,
+    Xochip
+}
+
+impl Platform {
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::Schip => "SCHIP",
+            Platform::Xochip => "XO-CHIP"
+        }
+    }
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::VIP,
+            Platform::Schip => Quirks::SCHIP,
+            Platform::Xochip => Quirks::XOCHIP
+        }
+    }
+
+    // XO-CHIP programs can address 64 KiB
+    pub fn ram_size(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::Schip => RAM_SIZE,
+            Platform::Xochip => XO_RAM_SIZE
+        }
+    }
+
+    // the newest platform whose instructions show up in the program's reachable code
+    pub fn detect(rom: &[u8]) -> Platform {
+        let listing = disassembler::disassemble(rom, RAM_INTPT_OFFSET as u16);
+        listing.lines.iter().filter_map(|line| match &line.item {
+            ListingItem::Instruction(inst) => Some(instruction_platform(inst)),
+            ListingItem::Data => None
+        }).max_by_key(|&platform| platform as u8).unwrap_or_default()
+    }
+}
+
+// the memory a ROM runs in, going by the platform of its database entry or, for an unknown ROM,
+// the instructions it uses, whatever quirks it ends up running with
+pub fn ram_size(rom: &[u8], info: Option<&RomInfo>) -> usize {
+    info.map(|info| info.platform).unwrap_or_else(|| Platform::detect(rom)).ram_size()
+}
+
+fn instruction_platform(inst: &Instruction) -> Platform {
+    match *inst {
+        Instruction::LD_IRXY(..) | Instruction::LD_RXYI(..) | Instruction::LD_IL(_)
+        | Instruction::PLANE(_) | Instruction::AUDIO | Instruction::PITCH(_) => Platform::Xochip,
+        Instruction::SCD(_) | Instruction::SCR | Instruction::SCL | Instruction::EXIT | Instruction::LOW
+        | Instruction::HIGH | Instruction::LD_RHF(_) | Instruction::LD_PRR(_) | Instruction::LD_RRP(_)
+        | Instruction::DRW(_, _, 0) => Platform::Schip,
+        _ => Platform::Chip8
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseError(String);
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+// what's known about a ROM, the settings left out fall back to the platform's or the emulator's defaults
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RomInfo {
+    pub title: String,
+    pub author: Option<String>,
+    pub year: Option<u16>,
+    pub platform: Platform,
+    pub quirks: Option<Quirks>,
+    pub instructions_per_frame: Option<u32>,
+    // host key names per keypad key, like the config's [keys] table
+    pub keys: BTreeMap<u8, Vec<String>>,
+    pub description: Option<String>
+}
+
+impl RomInfo {
+    pub fn quirks(&self) -> Quirks {
+        self.quirks.unwrap_or_else(|| self.platform.quirks())
+    }
+
+    // e.g. `Brix by Andreas Gustafsson (1990)`
+    pub fn full_title(&self) -> String {
+        let mut title = self.title.clone();
+        if let Some(author) = &self.author {
+            title += &format!(" by {}", author);
+        }
+        if let Some(year) = self.year {
+            title += &format!(" ({})", year);
+        }
+        title
+    }
+
+    // a best guess from the ROM's file name, `Title [Author, Year]` in the bundled ROMs, the
+    // platform its code is written for and the notes that come with it
+    pub fn import(file_stem: &str, rom: &[u8], notes: Option<&str>) -> RomInfo {
+        let mut info = RomInfo {
+            platform: Platform::detect(rom),
+            ..RomInfo::default()
+        };
+
+        let mut title = file_stem.to_string();
+        if let (Some(start), Some(end)) = (file_stem.find('['), file_stem.rfind(']')) {
+            if start < end {
+                let mut credits: Vec<&str> = file_stem[start + 1..end].split(',').map(str::trim).collect();
+                if let Some(year) = credits.last().and_then(|last| parse_year(last)) {
+                    info.year = Some(year);
+                    credits.pop();
+                }
+                let author = credits.join(", ");
+                info.author = (!author.is_empty()).then_some(author);
+                title = format!("{} {}", &file_stem[..start], &file_stem[end + 1..]);
+            }
+        }
+        info.title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if let Some(notes) = notes {
+            // some notes start with a `Field : value` header
+            for (field, value) in notes.lines().filter_map(|line| line.split_once(':')) {
+                let value = value.trim();
+                match field.trim().to_ascii_lowercase().as_str() {
+                    "author" if info.author.is_none() && !value.is_empty() => info.author = Some(value.to_string()),
+                    "date" | "year" if info.year.is_none() => info.year = value.split('/').filter_map(parse_year).next(),
+                    _ => {}
+                }
+            }
+            let description = notes.trim();
+            info.description = (!description.is_empty()).then(|| description.to_string());
+        }
+        info
+    }
+}
+
+fn parse_year(text: &str) -> Option<u16> {
+    text.trim().parse().ok().filter(|year| (1970..2100).contains(year))
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RomEntry {
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    year: Option<u16>,
+    platform: Platform,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quirks: Option<QuirksEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ipf: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    keys: BTreeMap<String, Names>
+}
+
+// quirks are stored by preset name, or as the flags that differ from the platform's preset
+#[derive(Serialize, Deserialize)]
+#[serde(untagged, expecting = "a quirks preset name or a table of known quirks")]
+enum QuirksEntry {
+    Preset(String),
+    Flags(QuirkFlags)
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct QuirkFlags {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shift_uses_vy: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index_increment: Option<IndexIncrement>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jump_uses_vx: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logic_resets_vf: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wrap_sprites: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    display_wait: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key_wait_release: Option<bool>
+}
+
+impl QuirkFlags {
+    fn diff(base: Quirks, quirks: Quirks) -> QuirkFlags {
+        fn changed<T: PartialEq>(base: T, value: T) -> Option<T> {
+            (base != value).then_some(value)
+        }
+        QuirkFlags {
+            shift_uses_vy: changed(base.shift_uses_vy, quirks.shift_uses_vy),
+            index_increment: changed(base.index_increment, quirks.index_increment),
+            jump_uses_vx: changed(base.jump_uses_vx, quirks.jump_uses_vx),
+            logic_resets_vf: changed(base.logic_resets_vf, quirks.logic_resets_vf),
+            wrap_sprites: changed(base.wrap_sprites, quirks.wrap_sprites),
+            display_wait: changed(base.display_wait, quirks.display_wait),
+            key_wait_release: changed(base.key_wait_release, quirks.key_wait_release)
+        }
+    }
+
+    fn apply(&self, base: Quirks) -> Quirks {
+        Quirks {
+            shift_uses_vy: self.shift_uses_vy.unwrap_or(base.shift_uses_vy),
+            index_increment: self.index_increment.unwrap_or(base.index_increment),
+            jump_uses_vx: self.jump_uses_vx.unwrap_or(base.jump_uses_vx),
+            logic_resets_vf: self.logic_resets_vf.unwrap_or(base.logic_resets_vf),
+            wrap_sprites: self.wrap_sprites.unwrap_or(base.wrap_sprites),
+            display_wait: self.display_wait.unwrap_or(base.display_wait),
+            key_wait_release: self.key_wait_release.unwrap_or(base.key_wait_release)
+        }
+    }
+}
+
+fn preset_name(quirks: Quirks) -> Option<&'static str> {
+    Quirks::PRESET_NAMES.iter().copied().find(|name| name.parse::<Quirks>().ok() == Some(quirks))
+}
+
+// ROM information keyed by the SHA-1 of the ROM, stored as a TOML file with one table per ROM
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RomDatabase {
+    roms: BTreeMap<Hash, RomInfo>
+}
+
+impl RomDatabase {
+    pub fn new() -> Self {
+        RomDatabase::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn get(&self, hash: &Hash) -> Option<&RomInfo> {
+        self.roms.get(hash)
+    }
+
+    pub fn insert(&mut self, hash: Hash, info: RomInfo) {
+        self.roms.insert(hash, info);
+    }
+
+    // the entries of `other` replace the ones already known
+    pub fn merge(&mut self, other: RomDatabase) {
+        self.roms.extend(other.roms);
+    }
+
+    // the database in the config directory, then the one next to the ROM, which takes precedence
+    pub fn default_paths(rom_path: &Path) -> Vec<PathBuf> {
+        let rom_dir = rom_path.parent().map(|dir| dir.join(DATABASE_FILE_NAME));
+        config::config_dir().map(|dir| dir.join(DATABASE_FILE_NAME)).into_iter().chain(rom_dir).collect()
+    }
+
+    pub fn parse(source: &str) -> Result<RomDatabase, DatabaseError> {
+        let entries: BTreeMap<String, RomEntry> = toml::from_str(source)
+            .map_err(|e| DatabaseError(e.to_string().trim_end().to_string()))?;
+        let mut database = RomDatabase::new();
+        for (hex, entry) in entries {
+            let hash = parse_hash(&hex).ok_or_else(|| DatabaseError(format!("\"{}\" isn't a SHA-1", hex)))?;
+            let quirks = match entry.quirks {
+                Some(QuirksEntry::Preset(preset)) => Some(preset.parse().map_err(|e| DatabaseError(format!("{}: {}", hex, e)))?),
+                Some(QuirksEntry::Flags(flags)) => Some(flags.apply(entry.platform.quirks())),
+                None => None
+            };
+            if entry.ipf == Some(0) {
+                return Err(DatabaseError(format!("{}: ipf must be at least 1", hex)));
+            }
+            let mut keys = BTreeMap::new();
+            for (key, names) in entry.keys {
+                let key = parse_keypad_key(&key).map_err(|e| DatabaseError(format!("{}: {}", hex, e)))?;
+                keys.insert(key, names.into_vec());
+            }
+            database.insert(hash, RomInfo {
+                title: entry.title,
+                author: entry.author,
+                year: entry.year,
+                platform: entry.platform,
+                quirks,
+                instructions_per_frame: entry.ipf,
+                keys,
+                description: entry.description
+            });
+        }
+        Ok(database)
+    }
+
+    pub fn load(path: &Path) -> Result<RomDatabase, DatabaseError> {
+        let source = fs::read_to_string(path).map_err(|e| DatabaseError(e.to_string()))?;
+        RomDatabase::parse(&source)
+    }
+
+    pub fn to_toml(&self) -> String {
+        let entries: BTreeMap<String, RomEntry> = self.roms.iter().map(|(hash, info)| (hash_to_hex(hash), RomEntry {
+            title: info.title.clone(),
+            author: info.author.clone(),
+            year: info.year,
+            platform: info.platform,
+            // only written when it isn't the platform's preset anyway
+            quirks: info.quirks.filter(|&quirks| quirks != info.platform.quirks()).map(|quirks| match preset_name(quirks) {
+                Some(preset) => QuirksEntry::Preset(preset.to_string()),
+                None => QuirksEntry::Flags(QuirkFlags::diff(info.platform.quirks(), quirks))
+            }),
+            ipf: info.instructions_per_frame,
+            description: info.description.clone(),
+            keys: info.keys.iter().map(|(key, names)| (format!("{:X}", key), Names::Many(names.clone()))).collect()
+        })).collect();
+        toml::to_string(&entries).expect("ROM entries always serialize")
+    }
+
+    // imports every `.ch8` file in `dir` along with the `.txt` notes of the same name, the
+    // notes of a ROM ending in `(alt)` are shared with the original
+    pub fn import_dir(dir: &Path) -> Result<RomDatabase, DatabaseError> {
+        let entries = fs::read_dir(dir).map_err(|e| DatabaseError(format!("{}: {}", dir.display(), e)))?;
+        let mut database = RomDatabase::new();
+        for entry in entries {
+            let path = entry.map_err(|e| DatabaseError(e.to_string()))?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("ch8") {
+                continue;
+            }
+            let stem = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let rom = fs::read(&path).map_err(|e| DatabaseError(format!("{}: {}", path.display(), e)))?;
+            let notes = [stem.as_str(), stem.trim_end_matches("(alt)").trim_end()].iter()
+                .map(|name| dir.join(format!("{}.txt", name)))
+                .find_map(|notes_path| fs::read(notes_path).ok())
+                .map(|notes| String::from_utf8_lossy(&notes).replace("\r\n", "\n"));
+            database.insert(rom_hash(&rom), RomInfo::import(&stem, &rom, notes.as_deref()));
+        }
+        Ok(database)
+    }
+}
+
+fn parse_hash(hex: &str) -> Option<Hash> {
+    if hex.len() != 40 || !hex.is_ascii() {
+        return None;
+    }
+    let mut hash = [0; 20];
+    for (i, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(hash)
+}
@@ -0,0 +1,274 @@
+use std::path::PathBuf;
+use std::thread;
+use crate::Chip8;
+use crate::cpu::{AUDIO_PATTERN_SIZE, ExecutionError};
+use crate::debugger::Debugger;
+use crate::frontend::{AudioSink, InputEvent, InputSource, VideoSink};
+use crate::keyboard::KeyEvent;
+use crate::movie;
+use crate::movie::{FrameInput, Movie};
+use crate::rewind::{DEFAULT_REWIND_FRAMES, RewindBuffer};
+use crate::savestate;
+use crate::timing::{FRAME_RATE, FrameClock, SPEEDS};
+
+// instructions shown on each side of the program counter by the debugger
+const DISASSEMBLY_WINDOW: u16 = 5;
+// recorded frames stepped back per frame while rewinding
+const MAX_REWIND_SPEED: usize = 8;
+
+// a movie being recorded, or played back in place of the frontend's keypad input
+pub enum MovieMode {
+    Recording(Movie),
+    Replaying { movie: Movie, frame: usize }
+}
+
+// drives a Chip8 one 60Hz frame at a time, feeding it input and handing its output to a frontend
+pub struct Runner {
+    pub system: Chip8,
+    pub debugger: Debugger,
+    // save state slots are written next to this file
+    pub rom_path: Option<PathBuf>,
+    pub instructions_per_frame: u32,
+    pub clock: FrameClock,
+    pub rewind: RewindBuffer,
+    rewinding: bool,
+    rewind_speed: usize,
+    pub movie: Option<MovieMode>,
+    // the machine as it was handed to the runner, restored on reset
+    initial_state: Vec<u8>,
+    // key events wait for the start of the next frame that runs
+    pending_keys: Vec<KeyEvent>,
+    // instructions already run in the current frame, a frame can be left midway by the debugger
+    frame_progress: u32,
+    is_playing_tone: bool,
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    audio_pitch: u8
+}
+
+impl Runner {
+    pub fn new(system: Chip8, instructions_per_frame: u32) -> Self {
+        let audio_pitch = system.cpu.pitch();
+        let initial_state = system.save_state();
+        Runner {
+            system,
+            debugger: Debugger::default(),
+            rom_path: None,
+            instructions_per_frame,
+            clock: FrameClock::new(FRAME_RATE),
+            rewind: RewindBuffer::new(DEFAULT_REWIND_FRAMES),
+            rewinding: false,
+            rewind_speed: 1,
+            movie: None,
+            initial_state,
+            pending_keys: Vec::new(),
+            frame_progress: 0,
+            is_playing_tone: false,
+            audio_pattern: None,
+            audio_pitch
+        }
+    }
+
+    pub fn show_debugger(&self, video: &mut dyn VideoSink) {
+        video.show_debugger(&self.debugger.describe(&self.system, DISASSEMBLY_WINDOW));
+    }
+
+    // runs until the frontend quits or the program exits
+    pub fn run(&mut self, video: &mut dyn VideoSink, audio: &mut dyn AudioSink, input: &mut dyn InputSource) -> Result<(), ExecutionError> {
+        while self.tick(video, audio, input)? {
+            thread::sleep(self.clock.delay_until_frame());
+        }
+        Ok(())
+    }
+
+    // runs the frames that are due at this point in time, returns false once the runner should stop
+    pub fn tick(&mut self, video: &mut dyn VideoSink, audio: &mut dyn AudioSink, input: &mut dyn InputSource) -> Result<bool, ExecutionError> {
+        let frames = self.clock.due_frames();
+        for _ in 0..frames {
+            if !self.run_frame(video, input)? {
+                return Ok(false);
+            }
+        }
+
+        // frames that were caught up on are only shown once
+        if frames > 0 {
+            if self.system.display.dirty {
+                video.present(&self.system.display);
+                self.system.display.dirty = false;
+            }
+            self.update_audio(audio);
+        }
+        Ok(true)
+    }
+
+    // polls the input once, then runs the rest of the frame's instructions and counts the timers down
+    fn run_frame(&mut self, video: &mut dyn VideoSink, input: &mut dyn InputSource) -> Result<bool, ExecutionError> {
+        for event in input.poll() {
+            match event {
+                InputEvent::Key(key_event) => self.pending_keys.push(key_event),
+                InputEvent::Quit => return Ok(false),
+                other => self.handle_event(other, video)
+            }
+        }
+
+        // playing back in reverse replaces running the frame
+        if self.rewinding {
+            self.rewind.step_back(&mut self.system, self.rewind_speed);
+            self.frame_progress = 0;
+            return Ok(true);
+        }
+
+        // the keypad only changes once per frame, at its start, which is what movies record
+        if self.frame_progress == 0 && !self.debugger.is_paused() {
+            self.update_keypad(video);
+        }
+
+        // a display wait ends the frame early
+        while self.frame_progress < self.instructions_per_frame && !self.system.cpu.is_waiting_for_vblank() {
+            if self.debugger.is_paused() {
+                return Ok(true);
+            }
+            // step the current instruction, halting the machine if it faults
+            self.system.step()?;
+            self.frame_progress += 1;
+            if self.system.cpu.is_halted() {
+                video.show_message("The program exited.");
+                return Ok(false);
+            }
+            if self.debugger.check(&self.system) {
+                self.show_debugger(video);
+            }
+        }
+
+        self.frame_progress = 0;
+        self.system.cpu.countdown_timers();
+        self.rewind.record(&self.system);
+        Ok(true)
+    }
+
+    fn update_keypad(&mut self, video: &mut dyn VideoSink) {
+        let key_events = std::mem::take(&mut self.pending_keys);
+        match &mut self.movie {
+            Some(MovieMode::Replaying { movie, frame }) => match movie.frames.get(*frame) {
+                Some(frame_input) => {
+                    let previous = FrameInput::capture(&self.system.keyboard).keys;
+                    self.system.keyboard.update_key_states(frame_input.events(previous));
+                    *frame += 1;
+                }
+                None => {
+                    // hand the machine over to the player where the recording ended
+                    self.end_movie(video);
+                    self.debugger.pause();
+                    self.show_debugger(video);
+                }
+            },
+            Some(MovieMode::Recording(movie)) => {
+                self.system.keyboard.update_key_states(key_events);
+                movie.frames.push(FrameInput::capture(&self.system.keyboard));
+            }
+            None => self.system.keyboard.update_key_states(key_events)
+        }
+    }
+
+    // stops the movie, a recording is returned with the checksum of the machine's current state
+    // while a replay is checked against its recorded checksum
+    pub fn end_movie(&mut self, video: &mut dyn VideoSink) -> Option<Movie> {
+        match self.movie.take()? {
+            MovieMode::Recording(mut movie) => {
+                movie.checksum = movie::checksum(&self.system);
+                Some(movie)
+            }
+            MovieMode::Replaying { movie, frame } => {
+                if frame < movie.frames.len() {
+                    video.show_message(&format!("The replay stopped after {} of {} frames.", frame, movie.frames.len()));
+                } else if movie::checksum(&self.system) == movie.checksum {
+                    video.show_message("The replay matches the recording.");
+                } else {
+                    video.show_message("ERROR: The replay diverged from the recording.");
+                }
+                None
+            }
+        }
+    }
+
+    fn handle_event(&mut self, event: InputEvent, video: &mut dyn VideoSink) {
+        match event {
+            InputEvent::Rewind(true) | InputEvent::LoadState(_) | InputEvent::Reset if self.movie.is_some() => {
+                video.show_message("Rewinding, resetting and loading states are disabled during movies.");
+            }
+            InputEvent::TogglePause => {
+                if self.debugger.is_paused() {
+                    self.debugger.resume();
+                } else {
+                    self.debugger.pause();
+                    self.show_debugger(video);
+                }
+            }
+            InputEvent::Step if self.debugger.is_paused() => self.debugger.step(),
+            InputEvent::StepOver if self.debugger.is_paused() => self.debugger.step_over(&self.system),
+            InputEvent::Debug(command) if self.debugger.apply(command, &self.system) => {
+                self.show_debugger(video);
+            }
+            InputEvent::SaveState(slot) => video.show_message(&self.save_slot(slot)),
+            InputEvent::LoadState(slot) => video.show_message(&self.load_slot(slot)),
+            InputEvent::Rewind(rewinding) => self.rewinding = rewinding,
+            InputEvent::RewindSpeed(faster) => {
+                self.rewind_speed = if faster { self.rewind_speed * 2 } else { self.rewind_speed / 2 }.clamp(1, MAX_REWIND_SPEED);
+                video.show_message(&format!("Rewinding {}x.", self.rewind_speed));
+            }
+            InputEvent::Reset => {
+                self.system.load_state(&self.initial_state).expect("the initial state is always valid");
+                self.rewind.clear();
+                self.frame_progress = 0;
+                video.show_message("Reset the machine.");
+            }
+            InputEvent::Speed(faster) => {
+                let current = SPEEDS.iter().position(|&speed| speed >= self.clock.speed()).unwrap_or(SPEEDS.len() - 1);
+                let next = if faster { (current + 1).min(SPEEDS.len() - 1) } else { current.saturating_sub(1) };
+                self.clock.set_speed(SPEEDS[next]);
+                video.show_message(&format!("Running at {}% speed.", SPEEDS[next]));
+            }
+            _ => {}
+        }
+    }
+
+    fn save_slot(&self, slot: usize) -> String {
+        let path = match &self.rom_path {
+            Some(rom_path) => savestate::slot_path(rom_path, slot),
+            None => return "ERROR: Save states need a ROM path".to_string()
+        };
+        match std::fs::write(&path, self.system.save_state()) {
+            Ok(_) => format!("Saved state to {}.", path.display()),
+            Err(e) => format!("ERROR: Couldn't save state to {}: {}", path.display(), e)
+        }
+    }
+
+    fn load_slot(&mut self, slot: usize) -> String {
+        let path = match &self.rom_path {
+            Some(rom_path) => savestate::slot_path(rom_path, slot),
+            None => return "ERROR: Save states need a ROM path".to_string()
+        };
+        match std::fs::read(&path).map_err(|e| e.to_string())
+            .and_then(|data| self.system.load_state(&data).map_err(|e| e.to_string())) {
+            Ok(_) => format!("Loaded state from {}.", path.display()),
+            Err(e) => format!("ERROR: Couldn't load state from {}: {}", path.display(), e)
+        }
+    }
+
+    fn update_audio(&mut self, audio: &mut dyn AudioSink) {
+        // hand any new XO-CHIP audio pattern or pitch over to the audio sink
+        let cpu = &self.system.cpu;
+        if cpu.audio_pattern() != self.audio_pattern.as_ref() || cpu.pitch() != self.audio_pitch {
+            self.audio_pattern = cpu.audio_pattern().copied();
+            self.audio_pitch = cpu.pitch();
+            if let Some(pattern) = self.audio_pattern {
+                audio.set_pattern(pattern, cpu.audio_playback_rate());
+            }
+        }
+
+        // play a tone if the cpu's sound timer is positive
+        if cpu.is_tone_on() != self.is_playing_tone {
+            self.is_playing_tone = cpu.is_tone_on();
+            audio.set_tone(self.is_playing_tone);
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+
+// bumped whenever the layout below changes, older states are rejected
+pub const SAVE_STATE_VERSION: u16 = 4;
+const SAVE_STATE_MAGIC: &[u8; 4] = b"C8SS";
+
+// save state slots are written next to the rom, e.g. "Pong.state1"
+pub fn slot_path(rom_path: &Path, slot: usize) -> PathBuf {
+    rom_path.with_extension(format!("state{}", slot))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveStateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    Invalid(&'static str)
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "not a chip8 save state"),
+            SaveStateError::UnsupportedVersion(version)
+                => write!(f, "unsupported save state version {} (expected {})", version, SAVE_STATE_VERSION),
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::Invalid(what) => write!(f, "save state contains an invalid {}", what)
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+// all values are written big endian, in the order the machine components write them
+pub struct StateWriter {
+    data: Vec<u8>
+}
+
+impl StateWriter {
+    pub(crate) fn new() -> Self {
+        Self::with_header(SAVE_STATE_MAGIC, SAVE_STATE_VERSION)
+    }
+
+    // for other files written with the same encoding, e.g. movies
+    pub(crate) fn with_header(magic: &[u8; 4], version: u16) -> Self {
+        let mut data = Vec::new();
+        data.extend_from_slice(magic);
+        data.extend_from_slice(&version.to_be_bytes());
+        StateWriter { data }
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub(crate) fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub(crate) fn write_bool(&mut self, value: bool) {
+        self.data.push(value.into());
+    }
+
+    pub(crate) fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub(crate) fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub(crate) fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub(crate) fn write_u128(&mut self, value: u128) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub(crate) fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Result<Self, SaveStateError> {
+        Self::with_header(data, SAVE_STATE_MAGIC, SAVE_STATE_VERSION)
+    }
+
+    pub(crate) fn with_header(data: &'a [u8], magic: &[u8; 4], expected_version: u16) -> Result<Self, SaveStateError> {
+        let mut reader = StateReader { data };
+        if reader.read_bytes(magic.len()).map_err(|_| SaveStateError::BadMagic)? != magic {
+            return Err(SaveStateError::BadMagic);
+        }
+        let version = reader.read_u16()?;
+        if version != expected_version {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        Ok(reader)
+    }
+
+    pub(crate) fn finish(self) -> Result<(), SaveStateError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(SaveStateError::Invalid("trailing data"))
+        }
+    }
+
+    pub(crate) fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.data.len() < length {
+            return Err(SaveStateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub(crate) fn read_array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub(crate) fn read_bool(&mut self) -> Result<bool, SaveStateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::Invalid("boolean"))
+        }
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        Ok(u16::from_be_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        Ok(u32::from_be_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_be_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u128(&mut self) -> Result<u128, SaveStateError> {
+        Ok(u128::from_be_bytes(self.read_array()?))
+    }
+}
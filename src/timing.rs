@@ -0,0 +1,62 @@
+use std::time::{Duration, Instant};
+
+// the timers count down and the display is shown once per frame
+pub const FRAME_RATE: u32 = 60;
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 10;
+// frames emulated without being shown when the host falls behind, any further lag is dropped
+pub const DEFAULT_MAX_SKIPPED_FRAMES: u32 = 5;
+// emulation speeds the speed hotkeys step through, in percent of real time
+pub const SPEEDS: [u32; 6] = [25, 50, 100, 200, 400, 800];
+
+// paces emulated frames against the host's monotonic clock
+pub struct FrameClock {
+    frame_rate: u32,
+    speed: u32,
+    frame_duration: Duration,
+    next_frame: Instant,
+    pub max_skipped_frames: u32
+}
+
+impl FrameClock {
+    pub fn new(frame_rate: u32) -> Self {
+        FrameClock {
+            frame_rate,
+            speed: 100,
+            frame_duration: Duration::from_secs(1) / frame_rate,
+            next_frame: Instant::now(),
+            max_skipped_frames: DEFAULT_MAX_SKIPPED_FRAMES
+        }
+    }
+
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    // runs frames faster or slower than real time, in percent
+    pub fn set_speed(&mut self, speed: u32) {
+        self.speed = speed.max(1);
+        self.frame_duration = Duration::from_secs(1) * 100 / (self.frame_rate * self.speed);
+    }
+
+    // the number of frames to emulate now to catch up with the host clock
+    pub fn due_frames(&mut self) -> u32 {
+        let now = Instant::now();
+        if now < self.next_frame {
+            return 0;
+        }
+
+        let behind = ((now - self.next_frame).as_nanos() / self.frame_duration.as_nanos()) as u32 + 1;
+        let frames = behind.min(self.max_skipped_frames + 1);
+        if frames < behind {
+            // too far behind to catch up, start over from now
+            self.next_frame = now + self.frame_duration;
+        } else {
+            self.next_frame += self.frame_duration * frames;
+        }
+        frames
+    }
+
+    pub fn delay_until_frame(&self) -> Duration {
+        self.next_frame.saturating_duration_since(Instant::now())
+    }
+}
@@ -0,0 +1,52 @@
+// Errors from the assembler, which point at the line and column of the offending token.
+
+use std::env;
+use std::fs;
+use chip8::assembler::{self, AssembleError};
+
+fn error(source: &str) -> AssembleError {
+    match assembler::assemble(source) {
+        Ok(bytes) => panic!("assembled to {:02X?}", bytes),
+        Err(e) => e
+    }
+}
+
+fn assert_error(source: &str, line: usize, column: usize, message: &str) {
+    let e = error(source);
+    assert_eq!((e.file.as_deref(), e.line, e.column), (None, line, column), "{}", e);
+    assert!(e.message.contains(message), "{}", e);
+    assert_eq!(e.to_string(), format!("{}:{}: {}", line, column, e.message));
+}
+
+#[test]
+fn errors_point_at_the_token() {
+    assert_error("CLS\n  JP nowhere", 2, 6, "unknown symbol \"nowhere\"");
+    assert_error("LD V0, 5 $", 1, 10, "unexpected character '$'");
+    assert_error("db \"text", 1, 4, "unterminated string");
+    assert_error("start: CLS\nstart: RET", 2, 1, "start");
+    assert_error("\n\nLD V0, 256", 3, 8, "out of range");
+    assert_error("FOO V1", 1, 1, "invalid instruction");
+}
+
+#[test]
+fn overflowing_values_are_errors() {
+    assert_error("db 9223372036854775807 + 1", 1, 26, "too large");
+    assert_error("define big 9223372036854775807\ndw 5 - big - big", 2, 14, "too large");
+    assert_error("db 0 - 9223372036854775807 - 1 - 1", 1, 34, "too large");
+    // the sum may pass through large values on its way
+    assert_eq!(assembler::assemble("db 9223372036854775807 - 9223372036854775800").unwrap(), [7]);
+}
+
+#[test]
+fn errors_in_included_files_name_the_file() {
+    let directory = env::temp_dir().join(format!("chip8-assembler-test-{}", std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("main.asm"), "CLS\ninclude \"sprites.asm\"\n").unwrap();
+    fs::write(directory.join("sprites.asm"), "db 1, 2\n  db 3, missing\n").unwrap();
+    let e = assembler::assemble_file(&directory.join("main.asm")).unwrap_err();
+    fs::remove_dir_all(&directory).unwrap();
+
+    assert!(e.file.as_deref().is_some_and(|file| file.ends_with("sprites.asm")), "{}", e);
+    assert_eq!((e.line, e.column), (2, 9), "{}", e);
+    assert!(e.to_string().ends_with("sprites.asm:2:9: unknown symbol \"missing\""), "{}", e);
+}
@@ -0,0 +1,156 @@
+// Runs programs through both backends of `Chip8::run` and checks that they leave the machine in
+// the same state after every frame: the bundled ROMs under each quirks preset with scripted
+// input, random programs that may rewrite themselves, and code changed from outside.
+
+use std::fs;
+use std::path::Path;
+use proptest::prelude::*;
+use chip8::{assembler, Chip8, memory};
+use chip8::blocks::Backend;
+use chip8::cpu::ExecutionError;
+use chip8::instructions::Instruction;
+use chip8::keyboard::KeyEvent;
+use chip8::quirks::Quirks;
+use chip8::random::RandomSource;
+
+const PRESETS: [(&str, Quirks); 4] = [("vip", Quirks::VIP), ("chip48", Quirks::CHIP48), ("schip", Quirks::SCHIP), ("xochip", Quirks::XOCHIP)];
+
+fn machine(quirks: Quirks, program: &[u8], backend: Backend) -> Chip8 {
+    let ram_size = if quirks == Quirks::XOCHIP { memory::XO_RAM_SIZE } else { memory::RAM_SIZE };
+    let mut system = Chip8::with_ram_size(quirks, ram_size);
+    system.load_program(program);
+    system.cpu.set_random_source(RandomSource::seeded(7));
+    system.backend = backend;
+    system
+}
+
+// a key goes down every few frames and comes back up a while later
+fn key_events(frame: u64) -> Vec<KeyEvent> {
+    let key = (frame / 7 % 16) as u8;
+    match frame % 7 {
+        0 => vec![KeyEvent::Pressed(key)],
+        4 => vec![KeyEvent::Released(key)],
+        _ => Vec::new()
+    }
+}
+
+// runs both backends frame by frame, returning where they first differ
+fn compare(quirks: Quirks, program: &[u8], frames: u64, instructions_per_frame: u32) -> Result<(), String> {
+    let mut interpreter = machine(quirks, program, Backend::Interpreter);
+    let mut blocks = machine(quirks, program, Backend::Blocks);
+    for frame in 0..frames {
+        interpreter.keyboard.update_key_states(key_events(frame));
+        blocks.keyboard.update_key_states(key_events(frame));
+        let expected = interpreter.run(instructions_per_frame);
+        let actual = blocks.run(instructions_per_frame);
+        if actual != expected {
+            return Err(format!("frame {}: ran {:?} instead of {:?}", frame, actual, expected));
+        }
+        if blocks.save_state() != interpreter.save_state() {
+            return Err(format!("frame {}: the machines differ, pc {:#05X} instead of {:#05X}",
+                frame, blocks.cpu.program_counter, interpreter.cpu.program_counter));
+        }
+        if expected.1.is_err() || interpreter.cpu.is_halted() {
+            break;
+        }
+        interpreter.cpu.countdown_timers();
+        blocks.cpu.countdown_timers();
+    }
+    Ok(())
+}
+
+#[test]
+fn bundled_roms_run_the_same() {
+    let mut roms: Vec<_> = fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("roms")).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ch8"))
+        .collect();
+    roms.sort();
+    assert!(!roms.is_empty());
+
+    let mut failures = Vec::new();
+    for path in &roms {
+        let program = fs::read(path).unwrap();
+        for (name, quirks) in PRESETS {
+            // an odd number of instructions per frame ends the frames in the middle of blocks
+            if let Err(e) = compare(quirks, &program, 120, 37) {
+                failures.push(format!("{} ({}): {}", path.file_name().unwrap().to_string_lossy(), name, e));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn rewritten_code_runs_the_same() {
+    // the first pass turns `ADD V2, 1` into `ADD V3, 5`, the second one rewrites the last jump
+    // into a jump to the middle of the first block
+    let source = "
+        start:  ADD V2, 1
+                LD I, start
+                LD V0, 0x73
+                LD V1, 0x05
+                LD [I], V1
+                ADD V4, 1
+                SE V4, 2
+                JP start
+                LD V0, 0x12
+                LD V1, 0x02
+                LD I, jump
+                LD [I], V1
+        jump:   JP start
+    ";
+    let program = assembler::assemble(source).unwrap_or_else(|e| panic!("{}", e));
+    for (_, quirks) in PRESETS {
+        compare(quirks, &program, 10, 3).unwrap();
+        compare(quirks, &program, 10, 100).unwrap();
+    }
+}
+
+#[test]
+fn memory_written_between_runs_is_translated_again() {
+    let program = assembler::assemble("start: ADD V0, 1\nADD V1, 1\nJP start").unwrap();
+    let mut system = machine(Quirks::VIP, &program, Backend::Blocks);
+    assert_eq!(system.run(6), (6, Ok(())));
+    system.ram.write_instruction(0x202, Instruction::ADD_RV(2, 3)).unwrap();
+    assert_eq!(system.run(3), (3, Ok(())));
+    system.ram.borrow_memory_mut()[0x200..0x202].copy_from_slice(&0x7405u16.to_be_bytes());
+    assert_eq!(system.run(3), (3, Ok(())));
+    assert_eq!(system.cpu.v_registers()[..5], [3, 2, 6, 0, 5]);
+
+    // a fault ends the run after the instructions before it
+    system.ram.write_instruction(0x202, Instruction::RET).unwrap();
+    assert_eq!(system.run(10), (1, Err(ExecutionError::StackUnderflow { address: 0x202 })));
+}
+
+// opcodes whose addresses are folded into the first 64 bytes of the program, so that jumps, calls
+// and stores land on the program itself, with more long loads and stores than chance would give
+fn program() -> impl Strategy<Value = Vec<u8>> {
+    let instruction = any::<u16>().prop_filter("not an instruction the program can run", |&opcode| {
+        Instruction::read(opcode).is_ok_and(|inst| !matches!(inst, Instruction::SYS(_)))
+    });
+    let opcode = prop_oneof![
+        6 => instruction,
+        1 => Just(0xF000),
+        1 => (0..16u16).prop_map(|x| 0xF055 | x << 8)
+    ];
+    prop::collection::vec(opcode, 32).prop_map(|opcodes| {
+        opcodes.into_iter().flat_map(|opcode| {
+            let opcode = match opcode >> 12 {
+                0x1 | 0x2 | 0xA | 0xB => opcode & 0xF000 | (0x200 + (opcode & 0x3F)),
+                _ => opcode
+            };
+            opcode.to_be_bytes()
+        }).collect()
+    })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2000))]
+
+    #[test]
+    fn random_programs_run_the_same(program in program(), preset in 0..PRESETS.len(), instructions_per_frame in 1u32..50) {
+        let (_, quirks) = PRESETS[preset];
+        prop_assert_eq!(compare(quirks, &program, 8, instructions_per_frame), Ok(()));
+    }
+}
@@ -0,0 +1,133 @@
+// The key config: the defaults, then the global tables, then the ROM database's keys for the ROM,
+// then the config's tables for the ROM, and the mistakes it refuses.
+
+use std::collections::BTreeMap;
+use chip8::config::{Hotkey, KeyConfig, DEFAULT_DEADZONE, DEFAULT_KEYS};
+use chip8::movie::Hash;
+use chip8::romdb::RomInfo;
+
+const ROM: Hash = [0xAB; 20];
+const OTHER_ROM: Hash = [0x01; 20];
+const ROM_TABLE: &str = "abababababababababababababababababababab";
+
+fn parse(source: &str) -> KeyConfig {
+    KeyConfig::parse(source, &ROM, None).unwrap_or_else(|e| panic!("{}", e))
+}
+
+fn error(source: &str, rom: Option<&RomInfo>) -> String {
+    KeyConfig::parse(source, &ROM, rom).unwrap_err().to_string()
+}
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+fn rom_with_keys(keys: &[(u8, &[&str])]) -> RomInfo {
+    RomInfo { keys: keys.iter().map(|&(key, list)| (key, names(list))).collect(), ..RomInfo::default() }
+}
+
+#[test]
+fn an_empty_config_has_the_defaults() {
+    let config = parse("");
+    assert_eq!(config, KeyConfig::default());
+    assert_eq!(config.keys[&0x4], names(&[DEFAULT_KEYS[4]]));
+    assert_eq!(config.hotkeys[&Hotkey::Pause], "F1");
+    assert_eq!(config.deadzone, DEFAULT_DEADZONE);
+}
+
+#[test]
+fn global_tables_change_the_defaults() {
+    let config = parse(r#"
+        deadzone = 0.25
+        [keys]
+        4 = "A"
+        8 = ["Q", "Left"]
+        F = ""
+        [buttons]
+        5 = "x"
+        [hotkeys]
+        pause = "P"
+        rewind = ""
+    "#);
+    assert_eq!(config.keys[&0x4], names(&["A"]));
+    assert_eq!(config.keys[&0x8], names(&["Q", "Left"]));
+    assert!(!config.keys.contains_key(&0xF));
+    assert_eq!(config.keys[&0x5], names(&[DEFAULT_KEYS[5]]));
+    assert_eq!(config.buttons[&0x5], names(&["x"]));
+    assert_eq!(config.hotkeys[&Hotkey::Pause], "P");
+    assert!(!config.hotkeys.contains_key(&Hotkey::Rewind));
+    assert_eq!(config.hotkeys[&Hotkey::Reset], "F2");
+    assert_eq!(config.deadzone, 0.25);
+}
+
+#[test]
+fn rom_tables_apply_over_the_rom_database_over_the_global_tables() {
+    let source = format!(r#"
+        [keys]
+        5 = "Space"
+        6 = "Right"
+        [hotkeys]
+        pause = "P"
+        [roms.{rom}]
+        deadzone = 0.5
+        [roms.{rom}.keys]
+        6 = "L"
+        [roms.{rom}.hotkeys]
+        pause = "Return"
+        [roms.0101010101010101010101010101010101010101.keys]
+        6 = "K"
+    "#, rom = ROM_TABLE);
+    let rom = rom_with_keys(&[(0x5, &["Up"]), (0x6, &["Down"]), (0x7, &[])]);
+    let config = KeyConfig::parse(&source, &ROM, Some(&rom)).unwrap();
+    // the database overrides the global table, the config's ROM table overrides the database
+    assert_eq!(config.keys[&0x5], names(&["Up"]));
+    assert_eq!(config.keys[&0x6], names(&["L"]));
+    assert!(!config.keys.contains_key(&0x7));
+    assert_eq!(config.hotkeys[&Hotkey::Pause], "Return");
+    assert_eq!(config.deadzone, 0.5);
+
+    // the tables of other ROMs are left alone
+    let config = KeyConfig::parse(&source, &OTHER_ROM, None).unwrap();
+    assert_eq!(config.keys[&0x5], names(&["Space"]));
+    assert_eq!(config.keys[&0x6], names(&["K"]));
+    assert_eq!(config.hotkeys[&Hotkey::Pause], "P");
+    assert_eq!(config.deadzone, DEFAULT_DEADZONE);
+}
+
+#[test]
+fn keys_bound_twice_are_errors() {
+    // "A" is also the default of keypad key 8
+    assert_eq!(error("[keys]\n4 = \"A\"", None), "\"A\" is bound to both keypad key 4 and keypad key 8");
+    assert_eq!(error("[keys]\n4 = [\"Left\", \"left\"]", None), "\"left\" is bound to both keypad key 4 and keypad key 4");
+    assert_eq!(error("[hotkeys]\nreset = \"1\"", None), "\"1\" is bound to both keypad key 0 and hotkey reset");
+    assert_eq!(error("[hotkeys]\nreset = \"F1\"", None), "\"F1\" is bound to both hotkey pause and hotkey reset");
+    assert_eq!(error("[buttons]\n1 = \"a\"", None), "\"a\" is bound to both keypad key 1 and keypad key 5");
+    let rom_table = format!("[roms.{}.keys]\n3 = \"3\"", ROM_TABLE);
+    assert_eq!(error(&rom_table, None), "\"3\" is bound to both keypad key 2 and keypad key 3");
+    assert_eq!(error("", Some(&rom_with_keys(&[(0x0, &["Escape"])]))), "\"Escape\" is bound to both keypad key 0 and hotkey quit");
+
+    // swapping keys around, or a button named like a host key, is fine
+    let config = parse("[keys]\n4 = \"A\"\n8 = \"Q\"\n[buttons]\n1 = \"1\"");
+    assert_eq!((&config.keys[&0x4], &config.keys[&0x8]), (&names(&["A"]), &names(&["Q"])));
+}
+
+#[test]
+fn invalid_configs_are_errors() {
+    assert_eq!(error("[keys]\n10 = \"A\"", None), "\"10\" isn't a keypad key, expected 0-F");
+    assert_eq!(error("[buttons]\nG = \"a\"", None), "\"G\" isn't a keypad key, expected 0-F");
+    assert_eq!(error("[hotkeys]\nfly = \"F6\"", None), "unknown hotkey \"fly\"");
+    assert_eq!(error("deadzone = 1.5", None), "the deadzone must be between 0 and 1, not 1.5");
+    assert!(error("[keyz]\n4 = \"A\"", None).contains("unknown field `keyz`"));
+    assert!(error("[keys]\n4 = 5", None).contains("line 2"));
+}
+
+#[test]
+fn the_default_bindings_can_all_be_rebound() {
+    let mut source = String::from("[keys]\n");
+    let layout: BTreeMap<u8, String> = (0..16).map(|key| (key, format!("Keypad {:X}", key))).collect();
+    for (key, name) in &layout {
+        source += &format!("{:X} = \"{}\"\n", key, name);
+    }
+    let config = parse(&source);
+    assert!(layout.iter().all(|(key, name)| config.keys[key] == [name.clone()]));
+}
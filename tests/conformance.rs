@@ -0,0 +1,178 @@
+// Runs test ROMs headlessly for a fixed number of frames with scripted input and compares the
+// screen with the golden images in tests/golden, drawn the way `chip8-headless --ascii` draws it.
+// `UPDATE_GOLDEN=1 cargo test --test conformance` rewrites the images from the current output.
+//
+// The programs in tests/roms are assembled on the fly, see the comment at the top of each one
+// for what it draws.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use chip8::{assembler, Chip8, memory, timing};
+use chip8::keyboard::KeyEvent;
+use chip8::quirks::Quirks;
+use chip8::random::RandomSource;
+
+const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+
+fn manifest_path(path: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(path)
+}
+
+fn bundled_rom(name: &str) -> Vec<u8> {
+    fs::read(manifest_path("roms").join(format!("{}.ch8", name))).unwrap()
+}
+
+fn test_rom(name: &str) -> Vec<u8> {
+    let path = manifest_path("tests/roms").join(format!("{}.asm", name));
+    assembler::assemble_file(&path).unwrap_or_else(|e| panic!("{}", e))
+}
+
+struct Run<'a> {
+    quirks: Quirks,
+    frames: u64,
+    instructions_per_frame: u32,
+    // `<frame> <down|up> <key>` lines, like the scripts of `chip8-headless --keys`
+    keys: &'a str
+}
+
+impl Default for Run<'_> {
+    fn default() -> Self {
+        Run {
+            quirks: Quirks::VIP,
+            frames: 60,
+            instructions_per_frame: timing::DEFAULT_INSTRUCTIONS_PER_FRAME,
+            keys: ""
+        }
+    }
+}
+
+fn parse_keys(script: &str) -> BTreeMap<u64, Vec<KeyEvent>> {
+    let mut events: BTreeMap<u64, Vec<KeyEvent>> = BTreeMap::new();
+    for line in script.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let key = u8::from_str_radix(words[2], 16).unwrap();
+        let event = match words[1] {
+            "down" => KeyEvent::Pressed(key),
+            "up" => KeyEvent::Released(key),
+            action => panic!("unknown key action \"{}\"", action)
+        };
+        events.entry(words[0].parse().unwrap()).or_default().push(event);
+    }
+    events
+}
+
+impl Run<'_> {
+    fn run(&self, rom: &[u8]) -> Chip8 {
+        let ram_size = if self.quirks == Quirks::XOCHIP { memory::XO_RAM_SIZE } else { memory::RAM_SIZE };
+        let mut system = Chip8::with_ram_size(self.quirks, ram_size);
+        system.load_program(rom);
+        system.cpu.set_random_source(RandomSource::seeded(0));
+
+        let mut keys = parse_keys(self.keys);
+        for frame in 0..self.frames {
+            system.keyboard.update_key_states(keys.remove(&frame).unwrap_or_default());
+            system.run_frame(self.instructions_per_frame).unwrap();
+        }
+        system
+    }
+}
+
+fn render(system: &Chip8) -> String {
+    let display = &system.display;
+    let pixels = display.borrow_display();
+    let mut out = String::new();
+    for y in 0..display.height() {
+        for column in pixels.iter().take(display.width()) {
+            out.push(ASCII_PIXELS[column[y] as usize]);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn assert_golden(name: &str, system: &Chip8) {
+    let path = manifest_path("tests/golden").join(format!("{}.txt", name));
+    let screen = render(system);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &screen).unwrap();
+        return;
+    }
+    let golden = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("couldn't read {} ({}), run with UPDATE_GOLDEN=1 to create it", path.display(), e));
+    assert!(screen == golden, "the screen doesn't match {}\nexpected:\n{}\ngot:\n{}", path.display(), golden, screen);
+}
+
+#[test]
+fn opcodes() {
+    let rom = test_rom("opcodes");
+    for (name, quirks) in [("vip", Quirks::VIP), ("schip", Quirks::SCHIP)] {
+        let system = Run { quirks, ..Run::default() }.run(&rom);
+        assert_golden(&format!("opcodes-{}", name), &system);
+    }
+}
+
+#[test]
+fn flags() {
+    let system = Run::default().run(&test_rom("flags"));
+    assert_golden("flags", &system);
+}
+
+#[test]
+fn quirks() {
+    let rom = test_rom("quirks");
+    for name in Quirks::PRESET_NAMES {
+        // enough instructions per frame for drawing without the display wait to reach 15
+        let run = Run { quirks: name.parse().unwrap(), instructions_per_frame: 100, ..Run::default() };
+        assert_golden(&format!("quirks-{}", name), &run.run(&rom));
+    }
+}
+
+#[test]
+fn keypad() {
+    let rom = test_rom("keypad");
+    let keys = "10 down 7\n20 up 7\n30 down 3\n40 up 3";
+    let system = Run { keys, ..Run::default() }.run(&rom);
+    assert_golden("keypad", &system);
+
+    // without waiting for the release, FX0A returns 10 frames sooner
+    let quirks = Quirks { key_wait_release: false, ..Quirks::VIP };
+    let system = Run { quirks, keys, ..Run::default() }.run(&rom);
+    assert_golden("keypad-no-release", &system);
+}
+
+#[test]
+fn ibm_logo() {
+    let system = Run::default().run(&bundled_rom("IBM Logo"));
+    assert_golden("ibm-logo", &system);
+}
+
+#[test]
+fn chip8_logo() {
+    let system = Run::default().run(&bundled_rom("Chip8 emulator Logo [Garstyciuks]"));
+    assert_golden("chip8-logo", &system);
+}
+
+#[test]
+fn sqrt_test() {
+    let system = Run { frames: 120, ..Run::default() }.run(&bundled_rom("SQRT Test [Sergey Naydenov, 2010]"));
+    assert_golden("sqrt-test", &system);
+}
+
+#[test]
+fn delay_timer_test() {
+    // lowers V3 from 0 to 255 with key 8, then starts counting it down with key 5
+    let keys = "5 down 8\n10 up 8\n15 down 5\n20 up 5";
+    let system = Run { frames: 60, keys, ..Run::default() }.run(&bundled_rom("Delay Timer Test [Matthew Mikolay, 2010]"));
+    assert_golden("delay-timer-test", &system);
+}
+
+#[test]
+fn keypad_test() {
+    // written for CHIP-48, draws the keypad over the first 33 frames and then lights up a key for a
+    // moment once it's pressed and released
+    let keys = "50 down 5\n54 up 5";
+    let run = Run { quirks: Quirks::CHIP48, frames: 60, keys, ..Run::default() };
+    assert_golden("keypad-test", &run.run(&bundled_rom("Keypad Test [Hap, 2006]")));
+}
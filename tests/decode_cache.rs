@@ -0,0 +1,79 @@
+// Programs that rewrite their own code, which `Chip8::step` has to notice despite caching the
+// instructions it decoded.
+
+use chip8::{assembler, Chip8};
+use chip8::instructions::Instruction;
+use chip8::quirks::Quirks;
+
+fn run(source: &str, steps: usize) -> Chip8 {
+    let mut system = Chip8::new(Quirks::VIP);
+    system.load_program(&assembler::assemble(source).unwrap_or_else(|e| panic!("{}", e)));
+    for _ in 0..steps {
+        system.step().unwrap();
+    }
+    system
+}
+
+#[test]
+fn stores_replace_executed_instructions() {
+    // the first pass turns `ADD V2, 1` into `ADD V3, 5` through FX55
+    let source = "
+        start:  ADD V2, 1
+                LD I, start
+                LD V0, 0x73
+                LD V1, 0x05
+                LD [I], V1
+                JP start
+    ";
+    let system = run(source, 6 * 3);
+    assert_eq!(system.cpu.v_registers()[2], 1);
+    assert_eq!(system.cpu.v_registers()[3], 10);
+}
+
+#[test]
+fn bcd_replaces_long_load_operands() {
+    // 42 is stored as 00 04 02 from the second byte of the long load, giving it the operand 0x0402
+    let source = "
+                LD V0, 42
+        load:   LD I, LONG 0xFFFF
+                LD I, load + 1
+                LD B, V0
+                JP load
+    ";
+    let system = run(source, 1 + 4 + 1);
+    assert_eq!(system.cpu.i_register(), 0x0402);
+}
+
+#[test]
+fn stores_replace_long_load_operands() {
+    let source = "
+        load:   LD I, LONG 0x1234
+                LD V0, 0x56
+                LD V1, 0x78
+                LD I, load + 2
+                LD [I], V1
+                JP load
+    ";
+    let system = run(source, 6 + 1);
+    assert_eq!(system.cpu.i_register(), 0x5678);
+}
+
+#[test]
+fn written_memory_is_decoded_again() {
+    let mut system = run("start: ADD V0, 1\nJP start", 2);
+    system.ram.borrow_memory_mut()[0x200..0x202].copy_from_slice(&0x7102u16.to_be_bytes());
+    system.step().unwrap();
+    system.ram.write_instruction(0x202, Instruction::ADD_RV(2, 3)).unwrap();
+    system.step().unwrap();
+    assert_eq!(system.cpu.v_registers()[..3], [1, 2, 3]);
+
+    // loading a program or a save state starts from scratch as well
+    let state = system.save_state();
+    system.load_program(&[0x63, 0x07]);
+    system.step().unwrap();
+    assert_eq!(system.cpu.v_registers()[3], 7);
+    system.load_state(&state).unwrap();
+    system.cpu.program_counter = 0x200;
+    system.step().unwrap();
+    assert_eq!(system.cpu.v_registers()[1], 4);
+}
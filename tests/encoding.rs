@@ -0,0 +1,61 @@
+// Property tests for going from an Instruction back to its opcode and writing it into memory.
+
+use proptest::prelude::*;
+use chip8::cpu::ExecutionError;
+use chip8::instructions::Instruction;
+use chip8::memory::{RAM, RAM_SIZE, XO_RAM_SIZE};
+
+// every instruction that can be read, along with the opcode it was read from
+fn instruction() -> impl Strategy<Value = (u16, Instruction)> {
+    any::<u16>().prop_filter_map("not an instruction", |opcode| Instruction::read(opcode).ok().map(|inst| (opcode, inst)))
+}
+
+// instructions of either length, the XO-CHIP long load included
+fn any_instruction() -> impl Strategy<Value = Instruction> {
+    prop_oneof![
+        instruction().prop_map(|(_, inst)| inst),
+        any::<u16>().prop_map(Instruction::LD_IL)
+    ]
+}
+
+proptest! {
+    #[test]
+    fn encodes_to_the_opcode_it_was_read_from((opcode, inst) in instruction()) {
+        prop_assert_eq!(u16::from(inst), opcode);
+        prop_assert_eq!(Instruction::read(u16::from(inst)).unwrap(), inst);
+
+        let mut bytes = Vec::new();
+        inst.write(&mut bytes);
+        prop_assert_eq!(bytes, opcode.to_be_bytes());
+    }
+
+    #[test]
+    fn long_loads_are_written_with_their_operand(operand in any::<u16>()) {
+        let inst = Instruction::LD_IL(operand);
+        prop_assert_eq!(Instruction::read_long(u16::from(inst), operand).unwrap(), inst);
+
+        let mut bytes = vec![0xAA];
+        inst.write(&mut bytes);
+        prop_assert_eq!(bytes.len(), 1 + inst.length() as usize);
+        prop_assert_eq!(&bytes[1..], &[0xF0, 0x00, (operand >> 8) as u8, operand as u8]);
+    }
+
+    #[test]
+    fn written_instructions_read_back(inst in any_instruction(), address in 0..RAM_SIZE, xo in any::<bool>()) {
+        let mut ram = RAM::new(if xo { XO_RAM_SIZE } else { RAM_SIZE });
+        let length = inst.length() as usize;
+        let before = ram.borrow_memory().to_vec();
+        let result = ram.write_instruction(address, inst);
+
+        if address + length > ram.size() {
+            prop_assert_eq!(result, Err(ExecutionError::MemoryOutOfRange { address, length }));
+            prop_assert_eq!(ram.borrow_memory(), &before[..]);
+        } else {
+            prop_assert_eq!(result, Ok(()));
+            prop_assert_eq!(ram.read_instruction(address).unwrap(), inst);
+            // only the instruction's own bytes change
+            let changed: Vec<usize> = (0..ram.size()).filter(|&i| ram.borrow_memory()[i] != before[i]).collect();
+            prop_assert!(changed.iter().all(|i| (address..address + length).contains(i)));
+        }
+    }
+}
@@ -0,0 +1,104 @@
+// FX0A across frames: which key edges complete the wait, depending on the key_wait_release quirk,
+// and the tone that sounds while the pressed key is held.
+
+use chip8::{assembler, Chip8};
+use chip8::keyboard::KeyEvent;
+use chip8::quirks::Quirks;
+
+const INSTRUCTIONS_PER_FRAME: u32 = 10;
+
+fn machine(key_wait_release: bool) -> Chip8 {
+    let mut system = Chip8::new(Quirks { key_wait_release, ..Quirks::VIP });
+    let program = assembler::assemble("LD V0, K\nLD V1, 1\nloop: JP loop").unwrap();
+    system.load_program(&program);
+    system
+}
+
+fn frame(system: &mut Chip8, events: Vec<KeyEvent>) {
+    system.keyboard.update_key_states(events);
+    system.run_frame(INSTRUCTIONS_PER_FRAME).unwrap();
+}
+
+fn is_waiting(system: &Chip8) -> bool {
+    system.cpu.program_counter == 0x200 && system.cpu.v_registers()[1] == 0
+}
+
+#[test]
+fn completes_on_release_by_default() {
+    let mut system = machine(Quirks::VIP.key_wait_release);
+    frame(&mut system, vec![]);
+    assert!(is_waiting(&system));
+    assert!(!system.cpu.is_tone_on());
+
+    // the key going down starts the tone, which lasts as long as it's held
+    frame(&mut system, vec![KeyEvent::Pressed(5)]);
+    assert!(is_waiting(&system));
+    assert!(system.cpu.is_tone_on());
+    frame(&mut system, vec![]);
+    assert!(is_waiting(&system));
+    assert!(system.cpu.is_tone_on());
+
+    // another key released meanwhile doesn't count
+    frame(&mut system, vec![KeyEvent::Pressed(9)]);
+    frame(&mut system, vec![KeyEvent::Released(9)]);
+    assert!(is_waiting(&system));
+
+    frame(&mut system, vec![KeyEvent::Released(5)]);
+    assert!(!is_waiting(&system));
+    assert_eq!(system.cpu.v_registers()[0], 5);
+    assert!(!system.cpu.is_tone_on());
+}
+
+#[test]
+fn completes_on_press_without_the_quirk() {
+    let mut system = machine(false);
+    frame(&mut system, vec![]);
+    assert!(is_waiting(&system));
+
+    frame(&mut system, vec![KeyEvent::Pressed(0xC)]);
+    assert!(!is_waiting(&system));
+    assert_eq!(system.cpu.v_registers()[0], 0xC);
+    assert!(!system.cpu.is_tone_on());
+}
+
+#[test]
+fn completes_with_the_lowest_key_pressed() {
+    let mut system = machine(false);
+    frame(&mut system, vec![]);
+    frame(&mut system, vec![KeyEvent::Pressed(0xE), KeyEvent::Pressed(3), KeyEvent::Pressed(7)]);
+    assert_eq!(system.cpu.v_registers()[0], 3);
+}
+
+#[test]
+fn keys_held_before_the_wait_have_to_be_pressed_again() {
+    for key_wait_release in [true, false] {
+        let mut system = machine(key_wait_release);
+        // the key goes down in the same frame FX0A starts, then stays down
+        frame(&mut system, vec![KeyEvent::Pressed(7)]);
+        frame(&mut system, vec![]);
+        assert!(is_waiting(&system));
+        assert!(!system.cpu.is_tone_on());
+
+        frame(&mut system, vec![KeyEvent::Released(7)]);
+        assert!(is_waiting(&system));
+
+        frame(&mut system, vec![KeyEvent::Pressed(7)]);
+        if key_wait_release {
+            assert!(is_waiting(&system));
+            assert!(system.cpu.is_tone_on());
+            frame(&mut system, vec![KeyEvent::Released(7)]);
+        }
+        assert!(!is_waiting(&system));
+        assert_eq!(system.cpu.v_registers()[0], 7);
+    }
+}
+
+#[test]
+fn a_tap_within_one_frame_completes_the_wait() {
+    // the key goes down and up between two frames, which still counts as a press and a release
+    let mut system = machine(true);
+    frame(&mut system, vec![]);
+    frame(&mut system, vec![KeyEvent::Pressed(2), KeyEvent::Released(2)]);
+    assert!(!is_waiting(&system));
+    assert_eq!(system.cpu.v_registers()[0], 2);
+}
@@ -0,0 +1,127 @@
+// Movies: what is recorded has to survive being written out and read back, replay to the
+// recorded checksum, and fail verification as soon as the replay can't match the recording.
+
+use std::fs;
+use std::path::Path;
+use chip8::memory;
+use chip8::keyboard::KeyEvent;
+use chip8::movie::{self, FrameInput, Movie, MovieError, RngKind, MOVIE_VERSION};
+use chip8::quirks::Quirks;
+use chip8::savestate::SaveStateError;
+
+fn rom() -> Vec<u8> {
+    fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("roms/Brix [Andreas Gustafsson, 1990].ch8")).unwrap()
+}
+
+// the paddle goes left, right and back, with a key tapped within a single frame now and then,
+// recorded the way the Runner records
+fn record(rom: &[u8], rng: RngKind) -> (Movie, Vec<u8>) {
+    let mut movie = Movie::new(rom, memory::RAM_SIZE, Quirks::VIP, rng, 42, 30);
+    let mut system = movie.create_system(rom).unwrap();
+    for frame in 0..600 {
+        let events = match frame % 60 {
+            0 => vec![KeyEvent::Pressed(4)],
+            25 => vec![KeyEvent::Released(4), KeyEvent::Pressed(6)],
+            50 => vec![KeyEvent::Released(6)],
+            55 => vec![KeyEvent::Pressed(1), KeyEvent::Released(1)],
+            _ => Vec::new()
+        };
+        system.keyboard.update_key_states(events);
+        movie.frames.push(FrameInput::capture(&system.keyboard));
+        system.run_frame(movie.instructions_per_frame).unwrap();
+    }
+    movie.checksum = movie::checksum(&system);
+    (movie, system.save_state())
+}
+
+#[test]
+fn movies_read_back_as_written() {
+    let rom = rom();
+    for rng in [RngKind::ChaCha, RngKind::Vip] {
+        let (mut movie, _) = record(&rom, rng);
+        movie.quirks = Quirks::XOCHIP;
+        movie.ram_size = memory::XO_RAM_SIZE;
+        assert!(movie.frames.iter().any(|frame| frame.pressed & frame.released != 0));
+        assert_eq!(Movie::from_bytes(&movie.to_bytes()), Ok(movie));
+    }
+}
+
+#[test]
+fn replays_verify_against_the_recording() {
+    let rom = rom();
+    for rng in [RngKind::ChaCha, RngKind::Vip] {
+        let (movie, state) = record(&rom, rng);
+        let replayed = movie.verify(&rom).unwrap();
+        assert_eq!(replayed.save_state(), state);
+        assert_eq!(movie::checksum(&replayed), movie.checksum);
+    }
+}
+
+#[test]
+fn diverging_replays_fail_verification() {
+    let rom = rom();
+    let (movie, _) = record(&rom, RngKind::ChaCha);
+    let diverged = Err(MovieError::ChecksumMismatch { frame: movie.frames.len() });
+
+    // the paddle stays put for a while once the game is under way
+    let mut edited = movie.clone();
+    for frame in &mut edited.frames[300..360] {
+        *frame = FrameInput { keys: 0, pressed: 0, released: 0 };
+    }
+    assert_eq!(edited.verify(&rom).map(|_| ()), diverged);
+
+    // Brix draws a single random number in that time, which other seeds may well share
+    assert!((43..53).any(|seed| Movie { seed, ..movie.clone() }.verify(&rom).map(|_| ()) == diverged));
+
+    // with the VIP quirks Brix waits for the display most of the time, a few spare instructions don't matter
+    let mut edited = movie.clone();
+    edited.instructions_per_frame = 5;
+    assert_eq!(edited.verify(&rom).map(|_| ()), diverged);
+
+    // a frame missing from the end
+    let mut edited = movie.clone();
+    edited.frames.pop();
+    assert_eq!(edited.verify(&rom).map(|_| ()), Err(MovieError::ChecksumMismatch { frame: movie.frames.len() - 1 }));
+
+    let mut edited = movie.clone();
+    edited.checksum[0] ^= 1;
+    assert_eq!(edited.verify(&rom).map(|_| ()), diverged);
+
+    let mut other_rom = rom.clone();
+    other_rom[0x10] ^= 1;
+    assert_eq!(movie.verify(&other_rom).map(|_| ()), Err(MovieError::WrongRom));
+}
+
+#[test]
+fn broken_movies_are_rejected() {
+    let (movie, _) = record(&rom(), RngKind::ChaCha);
+    let bytes = movie.to_bytes();
+
+    let mut wrong_magic = bytes.clone();
+    wrong_magic[..4].copy_from_slice(b"C8SS");
+    assert_eq!(Movie::from_bytes(&wrong_magic), Err(MovieError::BadMagic));
+    assert_eq!(Movie::from_bytes(&[]), Err(MovieError::BadMagic));
+
+    let mut other_version = bytes.clone();
+    other_version[4..6].copy_from_slice(&(MOVIE_VERSION + 1).to_be_bytes());
+    assert_eq!(Movie::from_bytes(&other_version), Err(MovieError::UnsupportedVersion(MOVIE_VERSION + 1)));
+
+    for length in 6..bytes.len() {
+        assert_eq!(Movie::from_bytes(&bytes[..length]), Err(MovieError::Invalid(SaveStateError::Truncated)), "{} bytes", length);
+    }
+    let mut longer = bytes.clone();
+    longer.push(0);
+    assert_eq!(Movie::from_bytes(&longer), Err(MovieError::Invalid(SaveStateError::Invalid("trailing data"))));
+
+    // a frame count far beyond the data ends in the same error rather than a huge allocation
+    let frame_count_at = bytes.len() - 20 - 6 * movie.frames.len() - 4;
+    assert_eq!(bytes[frame_count_at..frame_count_at + 4], (movie.frames.len() as u32).to_be_bytes());
+    let mut too_many_frames = bytes.clone();
+    too_many_frames[frame_count_at..frame_count_at + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert_eq!(Movie::from_bytes(&too_many_frames), Err(MovieError::Invalid(SaveStateError::Truncated)));
+
+    // the memory size follows the header and the ROM hash
+    let mut bad_memory_size = bytes.clone();
+    bad_memory_size[26..30].copy_from_slice(&0x10_0000u32.to_be_bytes());
+    assert_eq!(Movie::from_bytes(&bad_memory_size), Err(MovieError::Invalid(SaveStateError::Invalid("memory size"))));
+}
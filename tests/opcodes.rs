@@ -0,0 +1,539 @@
+// Checks Instruction::read and CPU::execute against a reference specification written separately
+// from them. Every one of the 65536 opcodes is decoded and compared with the reference decoding,
+// assembled back from its mnemonic, and executed from two different machine states under every
+// quirks preset, after which the registers, flags, memory, stack, timers, screen and program
+// counter have to match what the reference model computed.
+
+use chip8::{assembler, Chip8};
+use chip8::cpu::{ExecutionError, AUDIO_PATTERN_SIZE, RPL_FLAG_COUNT, STACK_SIZE};
+use chip8::display::{Framebuffer, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use chip8::instructions::Instruction;
+use chip8::keyboard::KeyEvent;
+use chip8::memory::{RAM_INTPT_OFFSET, RAM_SIZE};
+use chip8::quirks::{IndexIncrement, Quirks};
+
+struct Operands {
+    x: u8,
+    y: u8,
+    n: u8,
+    nn: u8,
+    nnn: u16
+}
+
+impl Operands {
+    fn of(opcode: u16) -> Self {
+        Operands {
+            x: (opcode >> 8 & 0xF) as u8,
+            y: (opcode >> 4 & 0xF) as u8,
+            n: (opcode & 0xF) as u8,
+            nn: (opcode & 0xFF) as u8,
+            nnn: opcode & 0xFFF
+        }
+    }
+}
+
+type Decode = fn(&Operands) -> Instruction;
+
+// the reference decoding, the first row whose pattern matches gives the instruction, the hex
+// digits of a pattern have to match the opcode's nibbles while X, Y and N match any
+const DECODING: &[(&str, Decode)] = &[
+    ("00CN", |o| Instruction::SCD(o.n)),
+    ("00E0", |_| Instruction::CLS),
+    ("00EE", |_| Instruction::RET),
+    ("00FB", |_| Instruction::SCR),
+    ("00FC", |_| Instruction::SCL),
+    ("00FD", |_| Instruction::EXIT),
+    ("00FE", |_| Instruction::LOW),
+    ("00FF", |_| Instruction::HIGH),
+    ("0NNN", |o| Instruction::SYS(o.nnn)),
+    ("1NNN", |o| Instruction::JP(o.nnn)),
+    ("2NNN", |o| Instruction::CALL(o.nnn)),
+    ("3XNN", |o| Instruction::SE_RV(o.x, o.nn)),
+    ("4XNN", |o| Instruction::SNE_RV(o.x, o.nn)),
+    ("5XY0", |o| Instruction::SE_RR(o.x, o.y)),
+    ("5XY2", |o| Instruction::LD_IRXY(o.x, o.y)),
+    ("5XY3", |o| Instruction::LD_RXYI(o.x, o.y)),
+    ("6XNN", |o| Instruction::LD_RV(o.x, o.nn)),
+    ("7XNN", |o| Instruction::ADD_RV(o.x, o.nn)),
+    ("8XY0", |o| Instruction::LD_RR(o.x, o.y)),
+    ("8XY1", |o| Instruction::OR(o.x, o.y)),
+    ("8XY2", |o| Instruction::AND(o.x, o.y)),
+    ("8XY3", |o| Instruction::XOR(o.x, o.y)),
+    ("8XY4", |o| Instruction::ADD_RR(o.x, o.y)),
+    ("8XY5", |o| Instruction::SUB(o.x, o.y)),
+    ("8XY6", |o| Instruction::SHR(o.x, o.y)),
+    ("8XY7", |o| Instruction::SUBN(o.x, o.y)),
+    ("8XYE", |o| Instruction::SHL(o.x, o.y)),
+    ("9XY0", |o| Instruction::SNE_RR(o.x, o.y)),
+    ("ANNN", |o| Instruction::LD_IV(o.nnn)),
+    ("BNNN", |o| Instruction::JP_RV(o.nnn)),
+    ("CXNN", |o| Instruction::RND(o.x, o.nn)),
+    ("DXYN", |o| Instruction::DRW(o.x, o.y, o.n)),
+    ("EX9E", |o| Instruction::SKP(o.x)),
+    ("EXA1", |o| Instruction::SKNP(o.x)),
+    ("F002", |_| Instruction::AUDIO),
+    ("FX01", |o| Instruction::PLANE(o.x)),
+    ("FX07", |o| Instruction::LD_RD(o.x)),
+    ("FX0A", |o| Instruction::LD_RK(o.x)),
+    ("FX15", |o| Instruction::LD_DR(o.x)),
+    ("FX18", |o| Instruction::LD_SR(o.x)),
+    ("FX1E", |o| Instruction::ADD_IR(o.x)),
+    ("FX29", |o| Instruction::LD_RF(o.x)),
+    ("FX30", |o| Instruction::LD_RHF(o.x)),
+    ("FX33", |o| Instruction::LD_BR(o.x)),
+    ("FX3A", |o| Instruction::PITCH(o.x)),
+    ("FX55", |o| Instruction::LD_IRR(o.x)),
+    ("FX65", |o| Instruction::LD_RRI(o.x)),
+    ("FX75", |o| Instruction::LD_PRR(o.x)),
+    ("FX85", |o| Instruction::LD_RRP(o.x))
+];
+
+fn matches(pattern: &str, opcode: u16) -> bool {
+    pattern.chars().enumerate().all(|(i, c)| match c.to_digit(16) {
+        Some(digit) => (opcode >> (12 - 4 * i) & 0xF) as u32 == digit,
+        None => true
+    })
+}
+
+fn reference_decode(opcode: u16) -> Option<Instruction> {
+    DECODING.iter().find(|(pattern, _)| matches(pattern, opcode)).map(|(_, decode)| decode(&Operands::of(opcode)))
+}
+
+#[test]
+fn decodes_every_opcode() {
+    for opcode in 0..=u16::MAX {
+        match (Instruction::read(opcode), reference_decode(opcode)) {
+            (Ok(inst), Some(expected)) => assert_eq!(inst, expected, "{:#06X}", opcode),
+            (Err(e), None) => assert_eq!(e.opcode(), opcode),
+            (Ok(inst), None) => panic!("{:#06X} isn't an instruction but was read as {:?}", opcode, inst),
+            (Err(_), Some(expected)) => panic!("{:#06X} should be read as {:?}", opcode, expected)
+        }
+    }
+}
+
+#[test]
+fn decodes_long_loads() {
+    for operand in [0x0000, 0x1234, 0xFFFF] {
+        let inst = Instruction::read_long(0xF000, operand).unwrap();
+        assert_eq!(inst, Instruction::LD_IL(operand));
+        assert_eq!(inst.length(), 4);
+    }
+    // anything else is read like a two byte instruction
+    assert_eq!(Instruction::read_long(0x00E0, 0x1234).unwrap(), Instruction::CLS);
+}
+
+#[test]
+fn mnemonics_assemble_back_to_the_opcode() {
+    for opcode in 0..=u16::MAX {
+        if let Ok(inst) = Instruction::read(opcode) {
+            let mnemonic = inst.to_string();
+            let bytes = assembler::assemble(&mnemonic).unwrap_or_else(|e| panic!("{:#06X} `{}`: {}", opcode, mnemonic, e));
+            assert_eq!(bytes, opcode.to_be_bytes(), "{:#06X} `{}`", opcode, mnemonic);
+        }
+    }
+    let long_load = Instruction::LD_IL(0xABCD).to_string();
+    assert_eq!(assembler::assemble(&long_load).unwrap(), [0xF0, 0x00, 0xAB, 0xCD]);
+}
+
+// everything about the machine an instruction can change, as far as it can be observed
+#[derive(Debug, Clone, PartialEq)]
+struct Snapshot {
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+    stack: Vec<u16>,
+    delay_timer: u8,
+    sound_timer: u8,
+    rpl_flags: [u8; RPL_FLAG_COUNT],
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    pitch: u8,
+    halted: bool,
+    waiting_for_vblank: bool,
+    memory: Vec<u8>,
+    hires: bool,
+    planes: u8,
+    // left out where the reference doesn't model the screen (scrolling)
+    screen: Option<Box<Framebuffer>>
+}
+
+impl Snapshot {
+    fn capture(system: &Chip8) -> Snapshot {
+        let cpu = &system.cpu;
+        Snapshot {
+            v: *cpu.v_registers(),
+            i: cpu.i_register(),
+            pc: cpu.program_counter,
+            stack: cpu.stack().to_vec(),
+            delay_timer: cpu.delay_timer(),
+            sound_timer: cpu.sound_timer(),
+            rpl_flags: *cpu.borrow_rpl_flags(),
+            audio_pattern: cpu.audio_pattern().copied(),
+            pitch: cpu.pitch(),
+            halted: cpu.is_halted(),
+            waiting_for_vblank: cpu.is_waiting_for_vblank(),
+            memory: system.ram.borrow_memory().to_vec(),
+            hires: system.display.is_hires(),
+            planes: system.display.selected_planes(),
+            screen: Some(Box::new(*system.display.borrow_display()))
+        }
+    }
+
+    fn word_at(&self, address: u16) -> u16 {
+        u16::from_be_bytes([self.memory[address as usize], self.memory[address as usize + 1]])
+    }
+
+    fn skip(&mut self) {
+        // over four bytes when the next instruction is a long load
+        self.pc += if self.word_at(self.pc) == 0xF000 { 4 } else { 2 };
+    }
+
+    // `length` bytes from I, which have to be within memory
+    fn i_range(&mut self, address: u16, length: usize) -> Result<usize, ExecutionError> {
+        let start = self.i as usize;
+        if start + length > self.memory.len() {
+            self.pc = address;
+            return Err(ExecutionError::MemoryOutOfRange { address: start, length });
+        }
+        Ok(start)
+    }
+
+    // XORs `rows` (most significant bit first) onto the first plane of the low resolution
+    // screen, returning whether a lit pixel was turned off
+    fn draw(&mut self, x: u8, y: u8, rows: &[u16], width: usize, wrap: bool) -> bool {
+        let screen = self.screen.as_mut().unwrap();
+        let (x, y) = (x as usize % DISPLAY_WIDTH, y as usize % DISPLAY_HEIGHT);
+        let mut collision = false;
+        for (row, &bits) in rows.iter().enumerate() {
+            for column in 0..width {
+                let (mut px, mut py) = (x + column, y + row);
+                if bits & (0x8000 >> column) == 0 {
+                    continue;
+                }
+                if px >= DISPLAY_WIDTH || py >= DISPLAY_HEIGHT {
+                    if !wrap {
+                        continue;
+                    }
+                    px %= DISPLAY_WIDTH;
+                    py %= DISPLAY_HEIGHT;
+                }
+                collision |= screen[px][py] & 1 != 0;
+                screen[px][py] ^= 1;
+            }
+        }
+        collision
+    }
+
+    fn clear_screen(&mut self, planes: u8) {
+        for pixel in self.screen.as_mut().unwrap().iter_mut().flatten() {
+            *pixel &= !planes;
+        }
+    }
+}
+
+// what executing the instruction at PC does according to the CHIP-8, SUPER-CHIP 1.1 and XO-CHIP
+// specifications, given the quirks and the held `keys`
+//
+// only the low resolution screen with the first plane selected is modelled, which is what the
+// fixtures start from, and RND is left to the caller
+fn reference_execute(s: &mut Snapshot, quirks: &Quirks, keys: u16) -> Result<(), ExecutionError> {
+    let address = s.pc;
+    let opcode = s.word_at(address);
+    let Operands { x, y, n, nn, nnn } = Operands::of(opcode);
+    let (xi, yi) = (x as usize, y as usize);
+    let (vx, vy) = (s.v[xi], s.v[yi]);
+    s.pc += 2;
+
+    match (opcode >> 12, x, y, n) {
+        (0x0, 0x0, 0xC, _) | (0x0, 0x0, 0xF, 0xB) | (0x0, 0x0, 0xF, 0xC) => s.screen = None,
+        (0x0, 0x0, 0xE, 0x0) => s.clear_screen(s.planes),
+        (0x0, 0x0, 0xE, 0xE) => match s.stack.pop() {
+            Some(return_address) => s.pc = return_address,
+            None => {
+                s.pc = address;
+                return Err(ExecutionError::StackUnderflow { address });
+            }
+        },
+        (0x0, 0x0, 0xF, 0xD) => s.halted = true,
+        (0x0, 0x0, 0xF, 0xE) | (0x0, 0x0, 0xF, 0xF) => {
+            s.hires = n == 0xF;
+            s.clear_screen(0xFF);
+        }
+        (0x0, ..) => {
+            s.pc = address;
+            return Err(ExecutionError::UnsupportedSys { address, target: nnn });
+        }
+        (0x1, ..) => s.pc = nnn,
+        (0x2, ..) => {
+            if s.stack.len() == STACK_SIZE {
+                s.pc = address;
+                return Err(ExecutionError::StackOverflow { address });
+            }
+            s.stack.push(s.pc);
+            s.pc = nnn;
+        }
+        (0x3, ..) => if vx == nn { s.skip() },
+        (0x4, ..) => if vx != nn { s.skip() },
+        (0x5, _, _, 0x0) => if vx == vy { s.skip() },
+        // VX to VY in that order, which can be downwards
+        (0x5, _, _, 0x2) | (0x5, _, _, 0x3) => {
+            let count = xi.abs_diff(yi) + 1;
+            let start = s.i_range(address, count)?;
+            for k in 0..count {
+                let register = if x <= y { xi + k } else { xi - k };
+                if n == 0x2 {
+                    s.memory[start + k] = s.v[register];
+                } else {
+                    s.v[register] = s.memory[start + k];
+                }
+            }
+        }
+        (0x6, ..) => s.v[xi] = nn,
+        (0x7, ..) => s.v[xi] = vx.wrapping_add(nn),
+        (0x8, _, _, 0x0) => s.v[xi] = vy,
+        (0x8, _, _, 0x1) | (0x8, _, _, 0x2) | (0x8, _, _, 0x3) => {
+            s.v[xi] = match n {
+                0x1 => vx | vy,
+                0x2 => vx & vy,
+                _ => vx ^ vy
+            };
+            if quirks.logic_resets_vf {
+                s.v[0xF] = 0;
+            }
+        }
+        // the flag is written last, so it wins when X is F
+        (0x8, _, _, 0x4) => {
+            let sum = vx as u16 + vy as u16;
+            s.v[xi] = sum as u8;
+            s.v[0xF] = (sum > 0xFF) as u8;
+        }
+        (0x8, _, _, 0x5) => {
+            s.v[xi] = vx.wrapping_sub(vy);
+            s.v[0xF] = (vx >= vy) as u8;
+        }
+        (0x8, _, _, 0x7) => {
+            s.v[xi] = vy.wrapping_sub(vx);
+            s.v[0xF] = (vy >= vx) as u8;
+        }
+        (0x8, _, _, 0x6) | (0x8, _, _, 0xE) => {
+            let source = if quirks.shift_uses_vy { vy } else { vx };
+            if n == 0x6 {
+                s.v[xi] = source >> 1;
+                s.v[0xF] = source & 0x01;
+            } else {
+                s.v[xi] = source << 1;
+                s.v[0xF] = source >> 7;
+            }
+        }
+        (0x9, _, _, 0x0) => if vx != vy { s.skip() },
+        (0xA, ..) => s.i = nnn,
+        (0xB, ..) => s.pc = nnn + if quirks.jump_uses_vx { vx } else { s.v[0] } as u16,
+        (0xC, ..) => {}
+        (0xD, ..) => {
+            let (width, length) = if n == 0 { (16, 32) } else { (8, n as usize) };
+            let start = s.i_range(address, length)?;
+            let sprite = &s.memory[start..start + length];
+            let rows: Vec<u16> = if n == 0 {
+                sprite.chunks(2).map(|row| (row[0] as u16) << 8 | row[1] as u16).collect()
+            } else {
+                sprite.iter().map(|&row| (row as u16) << 8).collect()
+            };
+            s.v[0xF] = s.draw(vx, vy, &rows, width, quirks.wrap_sprites) as u8;
+            s.waiting_for_vblank = quirks.display_wait;
+        }
+        (0xE, _, 0x9, 0xE) => if keys & 1 << (vx & 0xF) != 0 { s.skip() },
+        (0xE, _, 0xA, 0x1) => if keys & 1 << (vx & 0xF) == 0 { s.skip() },
+        (0xF, 0x0, 0x0, 0x0) => {
+            s.i = s.word_at(s.pc);
+            s.pc += 2;
+        }
+        (0xF, 0x0, 0x0, 0x2) => {
+            let start = s.i_range(address, AUDIO_PATTERN_SIZE)?;
+            s.audio_pattern = Some(s.memory[start..start + AUDIO_PATTERN_SIZE].try_into().unwrap());
+        }
+        (0xF, _, 0x0, 0x1) => s.planes = x & 0b11,
+        (0xF, _, 0x0, 0x7) => s.v[xi] = s.delay_timer,
+        // no key went down since the instruction started waiting, so it's executed again
+        (0xF, _, 0x0, 0xA) => s.pc = address,
+        (0xF, _, 0x1, 0x5) => s.delay_timer = vx,
+        (0xF, _, 0x1, 0x8) => s.sound_timer = vx,
+        (0xF, _, 0x1, 0xE) => s.i = s.i.wrapping_add(vx as u16),
+        (0xF, _, 0x2, 0x9) => s.i = (vx & 0xF) as u16 * 5,
+        (0xF, _, 0x3, 0x0) => s.i = 16 * 5 + (vx & 0xF) as u16 * 10,
+        (0xF, _, 0x3, 0x3) => {
+            let start = s.i_range(address, 3)?;
+            s.memory[start..start + 3].copy_from_slice(&[vx / 100, vx / 10 % 10, vx % 10]);
+        }
+        (0xF, _, 0x3, 0xA) => s.pitch = vx,
+        (0xF, _, 0x5, 0x5) | (0xF, _, 0x6, 0x5) => {
+            let start = s.i_range(address, xi + 1)?;
+            if y == 0x5 {
+                s.memory[start..=start + xi].copy_from_slice(&s.v[..=xi]);
+            } else {
+                s.v[..=xi].copy_from_slice(&s.memory[start..=start + xi]);
+            }
+            s.i = s.i.wrapping_add(match quirks.index_increment {
+                IndexIncrement::Unchanged => 0,
+                IndexIncrement::ByX => x as u16,
+                IndexIncrement::ByXPlusOne => x as u16 + 1
+            });
+        }
+        (0xF, _, 0x7, 0x5) => s.rpl_flags[..=xi].copy_from_slice(&s.v[..=xi]),
+        (0xF, _, 0x8, 0x5) => s.v[..=xi].copy_from_slice(&s.rpl_flags[..=xi]),
+        _ => {
+            s.pc = address;
+            return Err(ExecutionError::InvalidInstruction { address, opcode });
+        }
+    }
+    Ok(())
+}
+
+// the state a machine is set up in before an instruction is run
+struct Fixture {
+    v: [u8; 16],
+    i: u16,
+    stack_depth: usize,
+    keys: &'static [u8],
+    delay_timer: u8,
+    sound_timer: u8,
+    // the word after the instruction, which skips have to step over
+    next_word: u16,
+    // whether the screen already shows the font's 8 at the top left
+    drawn: bool
+}
+
+const FIXTURES: [Fixture; 2] = [
+    Fixture {
+        v: [0x00, 0x01, 0x02, 0x7F, 0x80, 0x81, 0xFF, 0xFE, 0x10, 0x05, 0x0A, 0x3C, 0xC3, 0x64, 0x99, 0x01],
+        i: 0x300,
+        stack_depth: 0,
+        keys: &[],
+        delay_timer: 5,
+        sound_timer: 0,
+        next_word: 0x6000,
+        drawn: false
+    },
+    // a full stack, I right before the end of memory and an XO-CHIP long load after the instruction
+    Fixture {
+        v: [0xFF, 0x80, 0x0F, 0x05, 0x0A, 0x00, 0x01, 0x40, 0xE7, 0x32, 0x3F, 0x3E, 0x1C, 0x7E, 0x11, 0xF0],
+        i: (RAM_SIZE - 6) as u16,
+        stack_depth: STACK_SIZE,
+        keys: &[0x5, 0xA, 0xF],
+        delay_timer: 0,
+        sound_timer: 7,
+        next_word: 0xF000,
+        drawn: true
+    }
+];
+
+impl Fixture {
+    // sets the machine up through instructions that the decoding and execution tests cover too
+    fn build(&self, quirks: Quirks) -> Chip8 {
+        let mut system = Chip8::new(quirks);
+        if self.drawn {
+            system.execute(Instruction::LD_RV(0, 0)).unwrap();
+            system.execute(Instruction::LD_RV(1, 8)).unwrap();
+            system.execute(Instruction::LD_RF(1)).unwrap();
+            system.execute(Instruction::DRW(0, 0, 5)).unwrap();
+            // ends the display wait the draw might have started
+            system.cpu.countdown_timers();
+        }
+        for _ in 0..self.stack_depth {
+            system.execute(Instruction::CALL(RAM_INTPT_OFFSET as u16)).unwrap();
+        }
+        for (r, &v) in self.v.iter().enumerate() {
+            system.execute(Instruction::LD_RV(r as u8, v)).unwrap();
+        }
+        system.execute(Instruction::LD_IV(0)).unwrap();
+        system.execute(Instruction::ADD_IR(0xF)).unwrap();
+        system.execute(Instruction::LD_IL(self.i)).unwrap();
+        system.execute(Instruction::LD_DR(0)).unwrap();
+        system.execute(Instruction::LD_RV(0, self.delay_timer)).unwrap();
+        system.execute(Instruction::LD_DR(0)).unwrap();
+        system.execute(Instruction::LD_RV(0, self.sound_timer)).unwrap();
+        system.execute(Instruction::LD_SR(0)).unwrap();
+        system.execute(Instruction::LD_RV(0, self.v[0])).unwrap();
+        system.keyboard.update_key_states(self.keys.iter().map(|&key| KeyEvent::Pressed(key)).collect());
+
+        let memory = system.ram.borrow_memory_mut();
+        for (offset, byte) in memory[self.i as usize..].iter_mut().take(16).enumerate() {
+            *byte = (offset as u8).wrapping_mul(37).wrapping_add(11);
+        }
+        memory[RAM_INTPT_OFFSET + 2..RAM_INTPT_OFFSET + 4].copy_from_slice(&self.next_word.to_be_bytes());
+        system.cpu.program_counter = RAM_INTPT_OFFSET as u16;
+        system
+    }
+
+    fn key_mask(&self) -> u16 {
+        self.keys.iter().fold(0, |mask, &key| mask | 1 << key)
+    }
+}
+
+#[test]
+fn executes_every_opcode_like_the_reference() {
+    for name in Quirks::PRESET_NAMES {
+        let quirks: Quirks = name.parse().unwrap();
+        for (index, fixture) in FIXTURES.iter().enumerate() {
+            for opcode in 0..=u16::MAX {
+                let mut system = fixture.build(quirks);
+                system.ram.borrow_memory_mut()[RAM_INTPT_OFFSET..RAM_INTPT_OFFSET + 2].copy_from_slice(&opcode.to_be_bytes());
+
+                let mut expected = Snapshot::capture(&system);
+                let expected_result = reference_execute(&mut expected, &quirks, fixture.key_mask());
+                let result = system.step();
+                let mut actual = Snapshot::capture(&system);
+
+                let context = format!("{:#06X} ({}) with the {} quirks on fixture {}", opcode,
+                    Instruction::read(opcode).map(|inst| inst.to_string()).unwrap_or_default(), name, index);
+                if opcode >> 12 == 0xC && expected_result.is_ok() {
+                    let x = (opcode >> 8 & 0xF) as usize;
+                    assert_eq!(actual.v[x] & !(opcode as u8), 0, "{}: RND isn't masked", context);
+                    expected.v[x] = actual.v[x];
+                }
+                if expected.screen.is_none() {
+                    actual.screen = None;
+                }
+                assert_eq!(result, expected_result, "{}", context);
+                assert!(actual == expected, "{}\nexpected: {:?}\ngot: {:?}", context, Diff(&expected, &actual), Diff(&actual, &expected));
+            }
+        }
+    }
+}
+
+// the fields of a snapshot that differ from another one, the full debug output being too large to read
+struct Diff<'a>(&'a Snapshot, &'a Snapshot);
+
+impl std::fmt::Debug for Diff<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (a, b) = (self.0, self.1);
+        let mut out = f.debug_struct("Snapshot");
+        macro_rules! field {
+            ($name:ident) => {
+                if a.$name != b.$name {
+                    out.field(stringify!($name), &a.$name);
+                }
+            };
+        }
+        field!(v);
+        field!(i);
+        field!(pc);
+        field!(stack);
+        field!(delay_timer);
+        field!(sound_timer);
+        field!(rpl_flags);
+        field!(audio_pattern);
+        field!(pitch);
+        field!(halted);
+        field!(waiting_for_vblank);
+        field!(hires);
+        field!(planes);
+        if a.memory != b.memory {
+            let changed: Vec<(usize, u8)> = a.memory.iter().enumerate()
+                .filter(|&(address, byte)| b.memory[address] != *byte).map(|(address, &byte)| (address, byte)).collect();
+            out.field("memory", &changed);
+        }
+        if a.screen != b.screen {
+            out.field("screen", &"differs");
+        }
+        out.finish()
+    }
+}
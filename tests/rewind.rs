@@ -0,0 +1,103 @@
+// The rewind buffer has to give back exactly the states it recorded, however far and however
+// often it is stepped back, and stop at the oldest frame it still holds.
+
+use std::fs;
+use std::path::Path;
+use chip8::{Chip8, memory};
+use chip8::keyboard::KeyEvent;
+use chip8::quirks::Quirks;
+use chip8::random::RandomSource;
+use chip8::rewind::RewindBuffer;
+
+const INSTRUCTIONS_PER_FRAME: u32 = 15;
+
+fn machine() -> Chip8 {
+    let rom = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("roms/Brix [Andreas Gustafsson, 1990].ch8")).unwrap();
+    let mut system = Chip8::new(Quirks::VIP);
+    system.load_program(&rom);
+    system.cpu.set_random_source(RandomSource::seeded(11));
+    system
+}
+
+// runs `frames` frames with the paddle moving, recording each one, and returns their states
+fn record(system: &mut Chip8, rewind: &mut RewindBuffer, frames: usize) -> Vec<Vec<u8>> {
+    let mut states = Vec::new();
+    for frame in 0..frames {
+        let key = if frame / 20 % 2 == 0 { 4 } else { 6 };
+        system.keyboard.update_key_states(vec![KeyEvent::Pressed(key), KeyEvent::Released(10 - key)]);
+        system.run_frame(INSTRUCTIONS_PER_FRAME).unwrap();
+        rewind.record(system);
+        states.push(system.save_state());
+    }
+    states
+}
+
+#[test]
+fn steps_back_to_the_recorded_states() {
+    let mut system = machine();
+    let mut rewind = RewindBuffer::new(100);
+    let states = record(&mut system, &mut rewind, 60);
+    assert_eq!(rewind.len(), 59);
+
+    // newest is frame 59
+    assert_eq!(rewind.step_back(&mut system, 1), 1);
+    assert_eq!(system.save_state(), states[58]);
+    assert_eq!(rewind.step_back(&mut system, 7), 7);
+    assert_eq!(system.save_state(), states[51]);
+    assert_eq!(rewind.step_back(&mut system, 0), 0);
+    assert_eq!(system.save_state(), states[51]);
+    assert_eq!(rewind.len(), 51);
+
+    // recording again continues from there
+    let mut states = states[..52].to_vec();
+    states.extend(record(&mut system, &mut rewind, 30));
+    for back in [3, 1, 20, 10] {
+        let newest = states.len() - 1;
+        assert_eq!(rewind.step_back(&mut system, back), back);
+        assert_eq!(system.save_state(), states[newest - back]);
+        states.truncate(newest - back + 1);
+    }
+}
+
+#[test]
+fn steps_back_at_most_the_capacity() {
+    let mut system = machine();
+    let mut rewind = RewindBuffer::new(10);
+    let states = record(&mut system, &mut rewind, 40);
+    assert_eq!(rewind.len(), 10);
+
+    assert_eq!(rewind.step_back(&mut system, 25), 10);
+    assert_eq!(system.save_state(), states[29]);
+    assert!(rewind.is_empty());
+    // the oldest frame stays put
+    assert_eq!(rewind.step_back(&mut system, 5), 0);
+    assert_eq!(system.save_state(), states[29]);
+
+    rewind.clear();
+    assert_eq!(rewind.step_back(&mut system, 5), 0);
+    assert_eq!(system.save_state(), states[29]);
+}
+
+#[test]
+fn steps_back_over_memory_size_changes_and_long_runs() {
+    let mut system = machine();
+    let mut rewind = RewindBuffer::new(10);
+    rewind.record(&system);
+    let small = system.save_state();
+
+    // a state with more memory, then one with all of that memory changed, which takes runs longer
+    // than a delta's counts can hold
+    let mut xo = Chip8::with_ram_size(Quirks::XOCHIP, memory::XO_RAM_SIZE);
+    xo.ram.borrow_memory_mut().iter_mut().enumerate().for_each(|(i, byte)| *byte = i as u8);
+    system.load_state(&xo.save_state()).unwrap();
+    rewind.record(&system);
+    let large = system.save_state();
+    system.ram.borrow_memory_mut().iter_mut().for_each(|byte| *byte = !*byte);
+    rewind.record(&system);
+
+    assert_eq!(rewind.step_back(&mut system, 1), 1);
+    assert_eq!(system.save_state(), large);
+    assert_eq!(rewind.step_back(&mut system, 1), 1);
+    assert_eq!(system.save_state(), small);
+    assert_eq!(system.ram.size(), memory::RAM_SIZE);
+}
@@ -0,0 +1,120 @@
+// The ROM database: what an import makes of a ROM's file name and notes, and entries that come
+// back from their TOML as they went in, whatever quirks they have.
+
+use std::collections::BTreeMap;
+use chip8::{assembler, memory};
+use chip8::quirks::{IndexIncrement, Quirks};
+use chip8::romdb::{self, Platform, RomDatabase, RomInfo};
+
+const NOTES: &str = "
+Blitz, by David Winter
+
+Author : somebody else
+Date   : 12/1991
+
+This game is a BOMBER clone. You are in a plane, and you must destroy the towers of a town.
+";
+
+fn rom(source: &str) -> Vec<u8> {
+    assembler::assemble(source).unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[test]
+fn imports_take_the_credits_from_the_file_name_then_the_notes() {
+    let chip8 = rom("CLS\nloop: JP loop");
+    let info = RomInfo::import("Blitz [David Winter]", &chip8, Some(NOTES));
+    assert_eq!(info.title, "Blitz");
+    assert_eq!(info.author.as_deref(), Some("David Winter"));
+    assert_eq!(info.year, Some(1991));
+    assert_eq!(info.platform, Platform::Chip8);
+    assert_eq!(info.quirks, None);
+    assert_eq!(info.description.as_deref(), Some(NOTES.trim()));
+
+    let info = RomInfo::import("Blitz", &chip8, Some(NOTES));
+    assert_eq!((info.author.as_deref(), info.year), (Some("somebody else"), Some(1991)));
+
+    let info = RomInfo::import("Breakout (Brix hack) [David Winter, Someone Else, 1997]", &chip8, Some("\n  \n"));
+    assert_eq!(info.title, "Breakout (Brix hack)");
+    assert_eq!(info.author.as_deref(), Some("David Winter, Someone Else"));
+    assert_eq!(info.year, Some(1997));
+    assert_eq!(info.description, None);
+
+    let info = RomInfo::import("Brick (Brix hack, 1990)", &chip8, None);
+    assert_eq!(info.title, "Brick (Brix hack, 1990)");
+    assert_eq!((info.author, info.year), (None, None));
+}
+
+#[test]
+fn the_platform_follows_the_instructions_used() {
+    let schip = rom("HIGH\nloop: JP loop");
+    let xochip = rom("LD I, long 0x1234\nloop: JP loop");
+    // instructions that are never reached don't count
+    let unreachable = rom("loop: JP loop\nPLANE 1");
+    for (rom, platform) in [(&schip, Platform::Schip), (&xochip, Platform::Xochip), (&unreachable, Platform::Chip8)] {
+        assert_eq!(RomInfo::import("ROM", rom, None).platform, platform);
+        assert_eq!(romdb::ram_size(rom, None), platform.ram_size());
+    }
+    assert_eq!(romdb::ram_size(&xochip, None), memory::XO_RAM_SIZE);
+
+    // the database's platform wins, and picks the memory whatever the quirks are
+    let info = RomInfo { platform: Platform::Xochip, quirks: Some(Quirks::VIP), ..RomInfo::default() };
+    assert_eq!(romdb::ram_size(&schip, Some(&info)), memory::XO_RAM_SIZE);
+    let info = RomInfo { platform: Platform::Chip8, quirks: Some(Quirks::XOCHIP), ..RomInfo::default() };
+    assert_eq!(romdb::ram_size(&xochip, Some(&info)), memory::RAM_SIZE);
+}
+
+#[test]
+fn databases_read_back_as_written() {
+    let custom = Quirks { display_wait: false, index_increment: IndexIncrement::ByX, ..Quirks::VIP };
+    let entries = [
+        RomInfo::import("Blitz [David Winter]", &rom("CLS"), Some(NOTES)),
+        RomInfo {
+            title: "Everything \"quoted\"".to_string(),
+            author: Some("Someone".to_string()),
+            year: Some(2008),
+            platform: Platform::Chip8,
+            quirks: Some(Quirks::SCHIP),
+            instructions_per_frame: Some(30),
+            keys: BTreeMap::from([(0x4, vec!["Left".to_string(), "A".to_string()]), (0xF, vec![])]),
+            description: Some("line one\nline two".to_string())
+        },
+        RomInfo { title: "Custom".to_string(), quirks: Some(custom), ..RomInfo::default() },
+        RomInfo { title: "Custom SCHIP".to_string(), platform: Platform::Schip, quirks: Some(Quirks { wrap_sprites: true, ..Quirks::SCHIP }), ..RomInfo::default() },
+        RomInfo { title: "Custom XO-CHIP".to_string(), platform: Platform::Xochip, quirks: Some(Quirks { key_wait_release: false, ..Quirks::VIP }), ..RomInfo::default() }
+    ];
+    let mut database = RomDatabase::new();
+    for (i, info) in entries.into_iter().enumerate() {
+        database.insert([i as u8; 20], info);
+    }
+
+    let toml = database.to_toml();
+    assert_eq!(RomDatabase::parse(&toml), Ok(database.clone()));
+    // a custom combination keeps only the flags that differ from the platform's preset
+    assert!(toml.contains("display_wait = false") && toml.contains("index_increment = \"by_x\""));
+    assert!(toml.contains("quirks = \"schip\""));
+    assert!(!toml.contains("shift_uses_vy"));
+
+    // the platform's own preset isn't written
+    let mut preset = RomDatabase::new();
+    preset.insert([9; 20], RomInfo { platform: Platform::Schip, quirks: Some(Quirks::SCHIP), ..RomInfo::default() });
+    assert!(!preset.to_toml().contains("quirks"));
+}
+
+#[test]
+fn quirks_are_a_preset_or_the_flags_to_change() {
+    let entry = |platform: &str, quirks: &str| format!(
+        "[0101010101010101010101010101010101010101]\ntitle = \"ROM\"\nplatform = \"{}\"\nquirks = {}\n", platform, quirks);
+    let quirks = |source: String| RomDatabase::parse(&source).map(|database| database.get(&[1; 20]).unwrap().quirks());
+
+    assert_eq!(quirks(entry("xochip", "\"chip48\"")), Ok(Quirks::CHIP48));
+    assert_eq!(quirks(entry("schip", "{ wrap_sprites = true, index_increment = \"by_x_plus_one\" }")),
+        Ok(Quirks { wrap_sprites: true, index_increment: IndexIncrement::ByXPlusOne, ..Quirks::SCHIP }));
+    assert_eq!(quirks(entry("chip8", "{}")), Ok(Quirks::VIP));
+
+    assert_eq!(quirks(entry("chip8", "\"cosmac\"")).unwrap_err().to_string(),
+        "0101010101010101010101010101010101010101: unknown quirks preset \"cosmac\" (expected one of vip, chip48, schip, xochip)");
+    for bad_flags in ["{ wrap = true }", "{ index_increment = \"by_y\" }", "{ display_wait = 1 }"] {
+        let error = quirks(entry("chip8", bad_flags)).unwrap_err().to_string();
+        assert!(error.contains("line 4") && error.contains("a quirks preset name or a table of known quirks"), "{}", error);
+    }
+}
@@ -0,0 +1,27 @@
+; Subroutines shared by the test programs, included at their end.
+; VA and VB hold the position of the next mark or digit, moving right and then down a row.
+
+; draws a filled square if V0 equals V1 and a cross otherwise
+check:
+    LD I, pass
+    SE V0, V1
+    LD I, fail
+    DRW VA, VB, 4
+    JP advance
+
+; draws the low digit of V0
+digit:
+    LD F, V0
+    DRW VA, VB, 5
+advance:
+    ADD VA, 5
+    SE VA, 60
+    RET
+    LD VA, 0
+    ADD VB, 6
+    RET
+
+pass:
+    db 0xF0, 0xF0, 0xF0, 0xF0
+fail:
+    db 0x90, 0x60, 0x60, 0x90
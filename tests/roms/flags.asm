@@ -0,0 +1,154 @@
+; Checks the result and then VF of each arithmetic instruction that sets a flag, including when
+; VF itself is the destination. Filled squares are right, crosses are wrong.
+
+    LD VA, 0x00
+    LD VB, 0x00
+
+    ; 8XY4 with and without a carry
+    LD V0, 0xFF
+    LD V2, 0x02
+    ADD V0, V2
+    LD V4, VF
+    LD V1, 0x01
+    CALL check
+    LD V0, V4
+    LD V1, 0x01
+    CALL check
+    LD V0, 0x10
+    LD V2, 0x20
+    ADD V0, V2
+    LD V4, VF
+    LD V1, 0x30
+    CALL check
+    LD V0, V4
+    LD V1, 0x00
+    CALL check
+
+    ; 8XY5, VF is 1 when there's no borrow, including for equal values
+    LD V0, 0x05
+    LD V2, 0x03
+    SUB V0, V2
+    LD V4, VF
+    LD V1, 0x02
+    CALL check
+    LD V0, V4
+    LD V1, 0x01
+    CALL check
+    LD V0, 0x03
+    LD V2, 0x05
+    SUB V0, V2
+    LD V4, VF
+    LD V1, 0xFE
+    CALL check
+    LD V0, V4
+    LD V1, 0x00
+    CALL check
+    LD V0, 0x05
+    LD V2, 0x05
+    SUB V0, V2
+    LD V4, VF
+    LD V1, 0x00
+    CALL check
+    LD V0, V4
+    LD V1, 0x01
+    CALL check
+
+    ; 8XY7
+    LD V0, 0x03
+    LD V2, 0x05
+    SUBN V0, V2
+    LD V4, VF
+    LD V1, 0x02
+    CALL check
+    LD V0, V4
+    LD V1, 0x01
+    CALL check
+    LD V0, 0x05
+    LD V2, 0x03
+    SUBN V0, V2
+    LD V4, VF
+    LD V1, 0xFE
+    CALL check
+    LD V0, V4
+    LD V1, 0x00
+    CALL check
+
+    ; 8XY6 and 8XYE, shifting a register into itself behaves the same with either shift quirk
+    LD V0, 0x05
+    SHR V0, V0
+    LD V4, VF
+    LD V1, 0x02
+    CALL check
+    LD V0, V4
+    LD V1, 0x01
+    CALL check
+    LD V0, 0x04
+    SHR V0, V0
+    LD V4, VF
+    LD V1, 0x02
+    CALL check
+    LD V0, V4
+    LD V1, 0x00
+    CALL check
+    LD V0, 0x81
+    SHL V0, V0
+    LD V4, VF
+    LD V1, 0x02
+    CALL check
+    LD V0, V4
+    LD V1, 0x01
+    CALL check
+    LD V0, 0x41
+    SHL V0, V0
+    LD V4, VF
+    LD V1, 0x82
+    CALL check
+    LD V0, V4
+    LD V1, 0x00
+    CALL check
+
+    ; with VF as the destination, the flag overwrites the result
+    LD VF, 0xFF
+    LD V2, 0x02
+    ADD VF, V2
+    LD V0, VF
+    LD V1, 0x01
+    CALL check
+    LD VF, 0x05
+    LD V2, 0x03
+    SUB VF, V2
+    LD V0, VF
+    LD V1, 0x01
+    CALL check
+    LD VF, 0x03
+    LD V2, 0x05
+    SUBN VF, V2
+    LD V0, VF
+    LD V1, 0x01
+    CALL check
+    LD VF, 0x04
+    SHR VF, VF
+    LD V0, VF
+    LD V1, 0x00
+    CALL check
+    LD VF, 0x80
+    SHL VF, VF
+    LD V0, VF
+    LD V1, 0x01
+    CALL check
+
+    ; VF as the source operand is read before the flag is written
+    LD VF, 0x01
+    LD V0, 0xFF
+    ADD V0, VF
+    LD V4, VF
+    LD V1, 0x00
+    CALL check
+    LD V0, V4
+    LD V1, 0x01
+    CALL check
+
+end:
+    JP end
+
+include "common.asm"
@@ -0,0 +1,46 @@
+; Draws the key FX0A returned and, in decimal, the frames it waited for it, then waits for key 3
+; to go down with EX9E and up again with EXA1, drawing a 3 and then an E.
+
+    LD VA, 0x00
+    LD VB, 0x00
+
+    LD V0, 0xFF
+    LD DT, V0
+    LD V0, K
+    LD V5, DT
+    CALL digit
+
+    LD V6, 0xFF
+    SUB V6, V5
+    LD I, scratch
+    LD B, V6
+    LD I, scratch
+    LD V2, [I]
+    LD V3, V1
+    LD V4, V2
+    CALL digit
+    LD V0, V3
+    CALL digit
+    LD V0, V4
+    CALL digit
+
+    LD V2, 0x03
+wait_down:
+    SKP V2
+    JP wait_down
+    LD V0, V2
+    CALL digit
+
+wait_up:
+    SKNP V2
+    JP wait_up
+    LD V0, 0x0E
+    CALL digit
+
+end:
+    JP end
+
+scratch:
+    db 0x00, 0x00, 0x00
+
+include "common.asm"
@@ -0,0 +1,214 @@
+; Runs every basic opcode on known values, each check draws a filled square when the result is
+; right and a cross when it isn't. The results don't depend on the quirks.
+
+    JP start
+
+; at 0x202, so `JP V0, jump_table` reads V2 rather than V0 under the jump quirk
+jump_table:
+    JP jumped_wrong
+    JP jumped_right
+
+start:
+    ; 00E0, whatever is left of this shows up over the first mark
+    LD I, fail
+    LD V0, 0x00
+    DRW V0, V0, 4
+    CLS
+
+    LD VA, 0x00
+    LD VB, 0x00
+
+    ; 6XNN, 7XNN
+    LD V0, 0x12
+    ADD V0, 0x34
+    LD V1, 0x46
+    CALL check
+
+    ; 7XNN wraps without touching VF
+    LD VF, 0x00
+    LD V0, 0xFF
+    ADD V0, 0x02
+    LD V1, 0x01
+    CALL check
+    LD V0, VF
+    LD V1, 0x00
+    CALL check
+
+    ; 8XY0
+    LD V2, 0x5A
+    LD V0, V2
+    LD V1, 0x5A
+    CALL check
+
+    ; 8XY1, 8XY2, 8XY3
+    LD V0, 0x0F
+    LD V2, 0xF0
+    OR V0, V2
+    LD V1, 0xFF
+    CALL check
+    LD V0, 0x3C
+    LD V2, 0x0F
+    AND V0, V2
+    LD V1, 0x0C
+    CALL check
+    LD V0, 0x3C
+    XOR V0, V2
+    LD V1, 0x33
+    CALL check
+
+    ; 3XNN skips when equal, then doesn't when not
+    LD V2, 0xF0
+    LD V0, 0x00
+    SE V2, 0xF0
+    LD V0, 0x01
+    LD V1, 0x00
+    CALL check
+    LD V0, 0x00
+    SE V2, 0x00
+    LD V0, 0x01
+    LD V1, 0x01
+    CALL check
+
+    ; 4XNN
+    LD V0, 0x00
+    SNE V2, 0x00
+    LD V0, 0x01
+    LD V1, 0x00
+    CALL check
+
+    ; 5XY0, 9XY0
+    LD V3, 0xF0
+    LD V0, 0x00
+    SE V2, V3
+    LD V0, 0x01
+    LD V1, 0x00
+    CALL check
+    LD V0, 0x00
+    SNE V2, V3
+    LD V0, 0x01
+    LD V1, 0x01
+    CALL check
+
+    ; 1NNN
+    LD V0, 0x00
+    JP jumped
+    LD V0, 0x01
+jumped:
+    LD V1, 0x00
+    CALL check
+
+    ; 2NNN, 00EE
+    LD V0, 0x00
+    CALL set_v0
+    LD V1, 0x42
+    CALL check
+
+    ; BNNN, with V0 and V2 equal so both ways land on the same entry
+    LD V0, 0x02
+    LD V2, 0x02
+    JP V0, jump_table
+jumped_wrong:
+    LD V0, 0x00
+    JP jump_done
+jumped_right:
+    LD V0, 0x01
+jump_done:
+    LD V1, 0x01
+    CALL check
+
+    ; FX33
+    LD V2, 234
+    LD I, scratch
+    LD B, V2
+    LD I, scratch
+    LD V2, [I]
+    LD V5, V1
+    LD V6, V2
+    LD V1, 0x02
+    CALL check
+    LD V0, V5
+    LD V1, 0x03
+    CALL check
+    LD V0, V6
+    LD V1, 0x04
+    CALL check
+
+    ; FX55, FX65
+    LD V0, 0x11
+    LD V1, 0x22
+    LD V2, 0x33
+    LD I, scratch
+    LD [I], V2
+    LD V0, 0x00
+    LD V1, 0x00
+    LD V2, 0x00
+    LD I, scratch
+    LD V2, [I]
+    LD V5, V1
+    LD V0, V2
+    LD V1, 0x33
+    CALL check
+    LD V0, V5
+    LD V1, 0x22
+    CALL check
+
+    ; FX1E
+    LD I, scratch
+    LD V0, 0x02
+    ADD I, V0
+    LD V0, [I]
+    LD V1, 0x33
+    CALL check
+
+    ; FX29 points at the font
+    LD V0, 0x01
+    LD F, V0
+    LD V0, [I]
+    LD V1, 0x20
+    CALL check
+
+    ; FX15, FX07
+    LD V0, 0x3C
+    LD DT, V0
+    LD V0, DT
+    LD V1, 0x3C
+    CALL check
+
+    ; CXNN with an empty mask
+    RND V0, 0x00
+    LD V1, 0x00
+    CALL check
+
+    ; EXA1 skips while the key is up
+    LD V2, 0x05
+    LD V0, 0x00
+    SKNP V2
+    LD V0, 0x01
+    LD V1, 0x00
+    CALL check
+
+    ; DXYN flags a collision when erasing a sprite
+    LD I, pass
+    LD V2, 56
+    LD V3, 26
+    DRW V2, V3, 4
+    LD V0, VF
+    LD V1, 0x00
+    CALL check
+    LD I, pass
+    DRW V2, V3, 4
+    LD V0, VF
+    LD V1, 0x01
+    CALL check
+
+end:
+    JP end
+
+set_v0:
+    LD V0, 0x42
+    RET
+
+scratch:
+    db 0x00, 0x00, 0x00, 0x00
+
+include "common.asm"
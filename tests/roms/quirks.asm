@@ -0,0 +1,77 @@
+; Draws one digit per quirk showing how the interpreter behaved:
+; 1. SHR V0, V1: 1 when VY is shifted, 8 when VX is
+; 2. VF after OR: 0 when the logic instructions reset it, 5 when they don't
+; 3. what FX65 leaves in I: A unchanged, B increased by X, C increased by X + 1
+; 4. BXNN: 0 when it jumps through V0, 2 when it jumps through VX
+; 5. DXYN per frame: 2 with the display wait, F when drawing doesn't wait
+; A bar is drawn across the right edge of the screen, it only shows on the left with wrapping.
+
+    JP start
+
+; at 0x202, so `JP V0, jump_table` reads V2 rather than V0 under the jump quirk
+jump_table:
+    JP jumped_v0
+    JP jumped_vx
+
+start:
+    LD VA, 0x00
+    LD VB, 0x00
+
+    LD V0, 0x10
+    LD V1, 0x02
+    SHR V0, V1
+    CALL digit
+
+    LD VF, 0x05
+    OR V0, V1
+    LD V0, VF
+    CALL digit
+
+    LD I, bytes
+    LD V1, [I]
+    LD V0, [I]
+    CALL digit
+
+    LD V0, 0x00
+    LD V2, 0x02
+    JP V0, jump_table
+jumped_v0:
+    LD V0, 0x00
+    JP jumped
+jumped_vx:
+    LD V0, 0x02
+jumped:
+    CALL digit
+
+    ; counts the sprites drawn until the delay timer runs out, up to 15
+    LD V0, 0x02
+    LD DT, V0
+    LD V0, 0x00
+    LD I, blank
+draw_loop:
+    DRW V0, V0, 1
+    ADD V0, 0x01
+    SNE V0, 0x0F
+    JP draw_done
+    LD V1, DT
+    SE V1, 0x00
+    JP draw_loop
+draw_done:
+    CALL digit
+
+    LD V0, 60
+    LD V1, 20
+    LD I, bar
+    DRW V0, V1, 1
+
+end:
+    JP end
+
+bytes:
+    db 0x0A, 0x0B, 0x0C, 0x0D
+blank:
+    db 0x00
+bar:
+    db 0xFF
+
+include "common.asm"
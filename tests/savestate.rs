@@ -0,0 +1,146 @@
+// Save states: a loaded state has to restore every part of the machine, so that it carries on
+// exactly like the machine it was saved from, and broken states are rejected without a panic
+// and without touching the machine.
+
+use chip8::{assembler, Chip8, memory};
+use chip8::quirks::Quirks;
+use chip8::random::RandomSource;
+use chip8::savestate::{SaveStateError, SAVE_STATE_VERSION};
+
+const PRESETS: [Quirks; 4] = [Quirks::VIP, Quirks::CHIP48, Quirks::SCHIP, Quirks::XOCHIP];
+
+// goes hi-res, draws on both planes, loads an audio pattern, sets the pitch, timers and RPL flags,
+// then keeps drawing random pixels from a subroutine
+const PROGRAM: &str = "
+            HIGH
+            PLANE 3
+            LD I, sprite
+            LD V0, 10
+            LD V1, 5
+            DRW V0, V1, 4
+            LD I, pattern
+            AUDIO
+            LD V2, 0x50
+            PITCH V2
+            LD V3, 40
+            LD DT, V3
+            LD ST, V3
+            LD R, V3
+            LD I, sprite
+    loop:   CALL dot
+            JP loop
+    dot:    RND V4, 0x7F
+            RND V5, 0x3F
+            ADD V6, 1
+            DRW V4, V5, 1
+            RET
+    sprite: db 0xF0, 0x90, 0x90, 0xF0
+    pattern: db 0x00, 0xFF, 0x00, 0xFF, 0x0F, 0xF0, 0x0F, 0xF0, 0x33, 0xCC, 0x33, 0xCC, 0x55, 0xAA, 0x55, 0xAA
+";
+
+fn machine(quirks: Quirks) -> Chip8 {
+    let ram_size = if quirks == Quirks::XOCHIP { memory::XO_RAM_SIZE } else { memory::RAM_SIZE };
+    let mut system = Chip8::with_ram_size(quirks, ram_size);
+    system.load_program(&assembler::assemble(PROGRAM).unwrap_or_else(|e| panic!("{}", e)));
+    system.cpu.set_random_source(RandomSource::seeded(3));
+    system
+}
+
+// a machine some way into the program, with a return address on the stack
+fn running_machine(quirks: Quirks) -> Chip8 {
+    let mut system = machine(quirks);
+    for _ in 0..5 {
+        system.run_frame(9).unwrap();
+    }
+    system.run(17).1.unwrap();
+    assert!(!system.cpu.stack().is_empty());
+    assert!(system.cpu.audio_pattern().is_some());
+    assert!(system.display.is_hires());
+    system
+}
+
+fn assert_same_machine(actual: &Chip8, expected: &Chip8) {
+    // the debug output lists every CPU field, including ones a state might have forgotten
+    assert_eq!(format!("{:?}", actual.cpu), format!("{:?}", expected.cpu));
+    assert_eq!(actual.ram.borrow_memory(), expected.ram.borrow_memory());
+    assert_eq!(actual.display.borrow_display(), expected.display.borrow_display());
+    assert_eq!(actual.display.is_hires(), expected.display.is_hires());
+    assert_eq!(actual.display.selected_planes(), expected.display.selected_planes());
+    assert_eq!(actual.quirks, expected.quirks);
+    assert_eq!(actual.save_state(), expected.save_state());
+}
+
+#[test]
+fn loaded_states_carry_on_like_the_saved_machine() {
+    for quirks in PRESETS {
+        let mut original = running_machine(quirks);
+        let state = original.save_state();
+
+        // a machine with other quirks, memory size and random source takes all of them from the state
+        let mut restored = Chip8::new(Quirks::VIP);
+        restored.cpu.set_random_source(RandomSource::vip(99));
+        restored.load_state(&state).unwrap();
+        assert_same_machine(&restored, &original);
+
+        // the random numbers and everything else keep matching
+        for _ in 0..20 {
+            original.run_frame(9).unwrap();
+            restored.run_frame(9).unwrap();
+            assert_same_machine(&restored, &original);
+        }
+    }
+}
+
+#[test]
+fn states_of_other_files_and_versions_are_rejected() {
+    let mut system = running_machine(Quirks::XOCHIP);
+    let state = system.save_state();
+
+    let mut wrong_magic = state.clone();
+    wrong_magic[0] ^= 0xFF;
+    assert_eq!(system.load_state(&wrong_magic), Err(SaveStateError::BadMagic));
+    assert_eq!(system.load_state(b"PNG"), Err(SaveStateError::BadMagic));
+
+    for version in [0, SAVE_STATE_VERSION - 1, SAVE_STATE_VERSION + 1] {
+        let mut other_version = state.clone();
+        other_version[4..6].copy_from_slice(&version.to_be_bytes());
+        assert_eq!(system.load_state(&other_version), Err(SaveStateError::UnsupportedVersion(version)));
+    }
+    assert_eq!(system.save_state(), state);
+}
+
+#[test]
+fn truncated_states_are_rejected() {
+    for quirks in PRESETS {
+        let mut system = running_machine(quirks);
+        let state = system.save_state();
+        // every cut through the header and the CPU, then a sample through memory and the display
+        let lengths = (4..256).chain((256..state.len()).step_by(61)).chain(state.len() - 16..state.len());
+        for length in lengths {
+            assert_eq!(system.load_state(&state[..length]), Err(SaveStateError::Truncated), "{} bytes", length);
+        }
+
+        let mut longer = state.clone();
+        longer.push(0);
+        assert_eq!(system.load_state(&longer), Err(SaveStateError::Invalid("trailing data")));
+        assert_eq!(system.save_state(), state);
+    }
+}
+
+#[test]
+fn corrupted_states_load_or_fail_without_panicking() {
+    let system = running_machine(Quirks::SCHIP);
+    let state = system.save_state();
+    // the header, the CPU and the start of memory, where the lengths and tags are
+    for position in 6..state.len().min(512) {
+        for value in [0x00, 0x01, 0x03, 0x11, 0x80, 0xFF] {
+            let mut corrupted = state.clone();
+            corrupted[position] = value;
+            let mut target = machine(Quirks::SCHIP);
+            let before = target.save_state();
+            if target.load_state(&corrupted).is_err() {
+                assert_eq!(target.save_state(), before);
+            }
+        }
+    }
+}